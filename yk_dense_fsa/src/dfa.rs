@@ -2,9 +2,13 @@
  * Generic dense deterministic finite automaton representation.
  */
 
+use std::borrow::Borrow;
 use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
 use crate::nfa::Automaton as NFA;
-use yk_intervals::{Interval, IntervalMap};
+use crate::dot;
+use crate::utf8;
+use yk_intervals::{Discrete, Interval, IntervalMap, IntervalSet, LowerBound, UpperBound};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct State(usize);
@@ -57,6 +61,10 @@ impl <T, AcceptingValue> Automaton<T, AcceptingValue> {
     pub fn states(&self) -> std::iter::Map<std::ops::RangeInclusive<usize>, fn(usize) -> State> {
         (0..=self.state_counter).map(|x| State(x))
     }
+
+    pub fn state_count(&self) -> usize {
+        self.state_counter + 1
+    }
 }
 
 impl <T, AcceptingValue> Automaton<T, AcceptingValue> where AcceptingValue : Default {
@@ -68,7 +76,9 @@ impl <T, AcceptingValue> Automaton<T, AcceptingValue> where AcceptingValue : Def
 impl <T, AcceptingValue> Automaton<T, AcceptingValue> where T : Clone + Ord {
     fn add_transition(&mut self, from: State, on: Interval<T>, to: State) {
         let from_map = self.transitions.entry(from).or_insert(IntervalMap::new());
-        from_map.insert_and_unify(on, to, |_| panic!());
+        // Neighboring intervals leading to the same state are merged, so the
+        // transition maps stay as small as possible
+        from_map.insert_and_coalesce(on, to, |_| panic!());
     }
 }
 
@@ -162,4 +172,580 @@ impl <T, AcceptingValue> From<NFA<T, AcceptingValue>> for Automaton<T, Accepting
     }
 }
 
-// TODO: Tests
+/**
+ * Matching.
+ */
+
+impl <T, AcceptingValue> Automaton<T, AcceptingValue> where T : Ord {
+    /// The state the automaton moves to from the given state on the symbol,
+    /// None if there's no transition for it.
+    pub fn step(&self, state: State, symbol: &T) -> Option<State> {
+        self.transitions_from(&state).and_then(|trs| trs.get(symbol)).cloned()
+    }
+
+    /// Checks if the whole input is accepted by the automaton.
+    pub fn accepts<I>(&self, input: I) -> bool where I : IntoIterator, I::Item : Borrow<T> {
+        let mut state = self.start;
+        for symbol in input {
+            match self.step(state, symbol.borrow()) {
+                Some(next) => state = next,
+                None => return false,
+            }
+        }
+        self.is_accepting(&state)
+    }
+
+    /// Finds the longest prefix of the input that the automaton accepts.
+    /// Returns the number of symbols in it and the value of the state it ends
+    /// in. Stops reading the input when there's no transition to take.
+    pub fn longest_match<I>(&self, input: I) -> Option<(usize, &AcceptingValue)>
+        where I : IntoIterator, I::Item : Borrow<T> {
+
+        let mut state = self.start;
+        let mut last_accepting = self.accepting_value(&state).map(|value| (0, value));
+        for (index, symbol) in input.into_iter().enumerate() {
+            match self.step(state, symbol.borrow()) {
+                Some(next) => state = next,
+                None => break,
+            }
+            if let Some(value) = self.accepting_value(&state) {
+                last_accepting = Some((index + 1, value));
+            }
+        }
+        last_accepting
+    }
+}
+
+/**
+ * Minimization.
+ */
+
+impl <T, AcceptingValue> Automaton<T, AcceptingValue> where T : Discrete, AcceptingValue : Clone + PartialEq {
+    /// Creates the equivalent automaton with the fewest states, using Moore's
+    /// partition refinement. States accepting with different values are never
+    /// merged. Dead and unreachable states are left out of the result.
+    pub fn minimize(&self) -> Self {
+        let live = self.live_states();
+
+        // Initially the states are partitioned by their accepting values
+        let mut values: Vec<Option<&AcceptingValue>> = Vec::new();
+        let mut partition: Vec<usize> = self.states()
+            .map(|state| {
+                let value = self.accepting_value(&state);
+                match values.iter().position(|v| *v == value) {
+                    Some(idx) => idx,
+                    None => {
+                        values.push(value);
+                        values.len() - 1
+                    },
+                }
+            })
+            .collect();
+        let mut partition_count = values.len();
+
+        // Split the partitions until the states in each of them transition
+        // to the same partitions
+        loop {
+            let mut signatures = BTreeMap::new();
+            let refined: Vec<usize> = self.states()
+                .map(|state| {
+                    let signature = (partition[state.id()], self.transition_signature(&state, &partition, &live));
+                    let next = signatures.len();
+                    *signatures.entry(signature).or_insert(next)
+                })
+                .collect();
+            partition = refined;
+            if signatures.len() == partition_count {
+                break;
+            }
+            partition_count = signatures.len();
+        }
+
+        // Build the result from the reachable partitions, with one
+        // representative state from each
+        let mut representatives = vec![None; partition_count];
+        for state in self.states() {
+            representatives[partition[state.id()]].get_or_insert(state);
+        }
+        let mut dfa = Self::new();
+        let mut partition_to_state = HashMap::new();
+        partition_to_state.insert(partition[self.start.id()], dfa.start);
+        let mut stk = vec![partition[self.start.id()]];
+        while let Some(part) = stk.pop() {
+            let from = partition_to_state[&part];
+            let representative = representatives[part].unwrap();
+            if let Some(value) = self.accepting_value(&representative) {
+                dfa.add_accepting_with_value(from, value.clone());
+            }
+            if let Some(trs) = self.transitions_from(&representative) {
+                for (on, to) in trs.iter().filter(|(_, to)| live.contains(to)) {
+                    let to_part = partition[to.id()];
+                    let to = match partition_to_state.get(&to_part) {
+                        Some(to) => *to,
+                        None => {
+                            let to = dfa.unique_state();
+                            partition_to_state.insert(to_part, to);
+                            stk.push(to_part);
+                            to
+                        },
+                    };
+                    dfa.add_transition(from, on.clone(), to);
+                }
+            }
+        }
+
+        dfa
+    }
+
+    // The transitions of the state in a comparable, canonical form: closed
+    // intervals with the partitions they lead to. Transitions into dead states
+    // are the same as no transition at all.
+    fn transition_signature(&self, state: &State, partition: &[usize], live: &HashSet<State>) -> Vec<(T, T, usize)> {
+        let mut transitions = IntervalMap::new();
+        if let Some(trs) = self.transitions_from(state) {
+            for (on, to) in trs.iter().filter(|(_, to)| live.contains(to)) {
+                transitions.insert_and_coalesce(on.clone(), partition[to.id()], |_| panic!());
+            }
+        }
+        transitions.normalize();
+        transitions.into_iter()
+            .map(|(on, part)| match (on.lower.to_included(), on.upper.to_included()) {
+                (Some(lower), Some(upper)) => (lower, upper, part),
+                _ => unreachable!("Normalized intervals are closed!"),
+            })
+            .collect()
+    }
+}
+
+/**
+ * Symbol equivalence classes.
+ */
+
+impl <T, AcceptingValue> Automaton<T, AcceptingValue> where T : Discrete {
+    /// Partitions the whole symbol domain into classes, where the symbols in
+    /// a class have the same transitions from every state. The classes are
+    /// numbered from 0, in the order of their smallest symbols. Table-driven
+    /// matchers only need a column for each class instead of each symbol.
+    pub fn symbol_classes(&self) -> IntervalMap<T, usize> {
+        // The symbols where the transitions can change
+        let mut boundaries = BTreeSet::new();
+        boundaries.insert(T::MIN);
+        for state in self.states() {
+            for (on, _) in self.transitions_from(&state).into_iter().flatten() {
+                if let Some(on) = on.to_closed() {
+                    boundaries.extend(on.lower.to_included());
+                    boundaries.extend(on.upper.to_included().and_then(|upper| upper.successor()));
+                }
+            }
+        }
+
+        // The segments between the boundaries are classified by where each
+        // state transitions on them
+        let mut signatures = BTreeMap::new();
+        let mut classes = IntervalMap::new();
+        let boundaries: Vec<T> = boundaries.into_iter().collect();
+        for (idx, lower) in boundaries.iter().enumerate() {
+            let upper = match boundaries.get(idx + 1) {
+                Some(next) => next.predecessor().unwrap(),
+                None => T::MAX,
+            };
+            let signature: Vec<Option<usize>> = self.states()
+                .map(|state| self.step(state, lower).map(|to| to.id()))
+                .collect();
+            let next = signatures.len();
+            let class = *signatures.entry(signature).or_insert(next);
+            let segment = Interval::with_bounds(LowerBound::Included(lower.clone()), UpperBound::Included(upper));
+            classes.insert_and_coalesce(segment, class, |_| panic!());
+        }
+        classes.normalize();
+        classes
+    }
+}
+
+/**
+ * UTF-8 encoding.
+ */
+
+impl <AcceptingValue> Automaton<char, AcceptingValue> where AcceptingValue : Clone {
+    /// Creates an automaton that reads the UTF-8 encoding of the characters
+    /// byte by byte, ending up in the states of this one after each complete
+    /// character. Invalid UTF-8 has no transitions, so it's never accepted.
+    pub fn to_utf8(&self) -> Automaton<u8, AcceptingValue> {
+        let mut nfa = NFA::new();
+        let states: Vec<_> = self.states()
+            .map(|s| if s == self.start { nfa.start } else { nfa.unique_state() })
+            .collect();
+
+        for from in self.states() {
+            if let Some(value) = self.accepting_value(&from) {
+                nfa.add_accepting_with_value(states[from.id()], value.clone());
+            }
+            for (on, to) in self.transitions_from(&from).into_iter().flatten() {
+                let (lower, upper) = match on.to_closed() {
+                    Some(on) => (on.lower.to_included().unwrap(), on.upper.to_included().unwrap()),
+                    None => continue,
+                };
+                // Each sequence gets its own states between the bytes, the
+                // determinization merges the common prefixes
+                for sequence in utf8::sequences(lower, upper) {
+                    let mut current = states[from.id()];
+                    for (idx, bytes) in sequence.iter().enumerate() {
+                        let next = if idx + 1 == sequence.len() { states[to.id()] } else { nfa.unique_state() };
+                        nfa.add_transition(current, *bytes, next);
+                        current = next;
+                    }
+                }
+            }
+        }
+
+        // After reading the same bytes the automaton can only be in a single
+        // state of the original one, so the values never need unifying
+        Automaton::from_nfa(nfa, |_, _| unreachable!("UTF-8 is a prefix code!"))
+    }
+}
+
+/**
+ * Boolean operations.
+ */
+
+impl <T, AcceptingValue> Automaton<T, AcceptingValue> where T : Clone + Ord {
+    /// Creates the automaton that accepts exactly the inputs both automata
+    /// accept, with the product construction. Only the state pairs reachable
+    /// from the start pair are constructed.
+    pub fn intersection<OtherValue>(&self, other: &Automaton<T, OtherValue>) -> Automaton<T> {
+        let mut dfa = Automaton::new();
+        let mut pair_to_state = HashMap::new();
+        let mut stk = Vec::new();
+
+        pair_to_state.insert((self.start, other.start), dfa.start);
+        stk.push((self.start, other.start, dfa.start));
+
+        while let Some((left, right, state)) = stk.pop() {
+            if self.is_accepting(&left) && other.is_accepting(&right) {
+                dfa.add_accepting(state);
+            }
+
+            let (left_trs, right_trs) = match (self.transitions_from(&left), other.transitions_from(&right)) {
+                (Some(l), Some(r)) => (l, r),
+                _ => continue,
+            };
+            for (l_iv, l_to) in left_trs {
+                for (r_iv, r_to) in right_trs.overlapping(l_iv) {
+                    let on = match l_iv.intersection(r_iv) {
+                        Some(iv) => iv,
+                        None => continue,
+                    };
+                    let to = match pair_to_state.get(&(*l_to, *r_to)) {
+                        Some(to) => *to,
+                        None => {
+                            let to = dfa.unique_state();
+                            pair_to_state.insert((*l_to, *r_to), to);
+                            stk.push((*l_to, *r_to, to));
+                            to
+                        },
+                    };
+                    dfa.add_transition(state, on, to);
+                }
+            }
+        }
+
+        dfa
+    }
+}
+
+impl <T, AcceptingValue> Automaton<T, AcceptingValue> where T : Discrete {
+    /// Creates the automaton that accepts exactly the inputs this one rejects.
+    /// The states are kept, and the missing transitions are completed with a
+    /// dead state, which becomes accepting.
+    pub fn complement(&self) -> Automaton<T> {
+        let mut dfa = Automaton::new();
+        dfa.state_counter = self.state_counter;
+        dfa.start = self.start;
+        let dead = dfa.unique_state();
+
+        for state in self.states() {
+            let mut covered = IntervalSet::new();
+            if let Some(trs) = self.transitions_from(&state) {
+                for (on, to) in trs {
+                    covered.insert(on.clone());
+                    dfa.add_transition(state, on.clone(), *to);
+                }
+            }
+            for on in covered.complement() {
+                dfa.add_transition(state, on, dead);
+            }
+            if !self.is_accepting(&state) {
+                dfa.add_accepting(state);
+            }
+        }
+        dfa.add_transition(dead, Interval::full().to_closed().unwrap(), dead);
+        dfa.add_accepting(dead);
+
+        dfa
+    }
+}
+
+impl <T, AcceptingValue> Automaton<T, AcceptingValue> {
+    /// The states from which an accepting state can be reached. The others
+    /// are dead ends, they can only make matching fail.
+    pub fn live_states(&self) -> HashSet<State> {
+        let mut predecessors: HashMap<State, Vec<State>> = HashMap::new();
+        for (from, trs) in &self.transitions {
+            for (_, to) in trs {
+                predecessors.entry(*to).or_default().push(*from);
+            }
+        }
+
+        let mut live: HashSet<State> = self.accepting.keys().cloned().collect();
+        let mut stk: Vec<State> = live.iter().cloned().collect();
+        while let Some(state) = stk.pop() {
+            for pred in predecessors.get(&state).into_iter().flatten() {
+                if live.insert(*pred) {
+                    stk.push(*pred);
+                }
+            }
+        }
+        live
+    }
+}
+
+/**
+ * Graphviz export.
+ */
+
+impl <T, AcceptingValue> Automaton<T, AcceptingValue> where T : Discrete + fmt::Display {
+    /// Creates a Graphviz DOT representation of the automaton. The transitions
+    /// between two states are drawn as a single edge, labeled with the
+    /// intervals, and the accepting states are labeled with their value, as
+    /// formatted by the given function.
+    pub fn to_dot<F>(&self, format_value: F) -> String where F : Fn(&AcceptingValue) -> String {
+        let mut result = dot::header(self.start.id());
+        for state in self.states() {
+            result.push_str(&dot::state_node(state.id(), self.accepting_value(&state).map(&format_value)));
+        }
+        for state in self.states() {
+            let mut edges: BTreeMap<usize, Vec<Interval<T>>> = BTreeMap::new();
+            for (on, to) in self.transitions_from(&state).into_iter().flatten() {
+                edges.entry(to.id()).or_default().push(on.clone());
+            }
+            for (to, intervals) in edges {
+                result.push_str(&format!("    {} -> {} [label=\"{}\"];\n", state.id(), to, dot::interval_label(intervals)));
+            }
+        }
+        result.push_str("}\n");
+        result
+    }
+}
+
+// Tests ///////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod dfa_tests {
+    use super::*;
+    use yk_regex_parse as regex;
+
+    fn dfa(source: &str) -> Automaton<char> {
+        NFA::from(regex::parse(source).unwrap()).into()
+    }
+
+    fn accepts<A>(dfa: &Automaton<char, A>, input: &str) -> bool {
+        dfa.accepts(input.chars())
+    }
+
+    #[test]
+    fn intersection() {
+        let both = dfa(r"[a-z]+").intersection(&dfa(r"...."));
+        assert!(accepts(&both, "abcd"));
+        assert!(!accepts(&both, "abc"));
+        assert!(!accepts(&both, "ab1d"));
+        assert!(!accepts(&both, "abcde"));
+    }
+
+    #[test]
+    fn disjunct_intersection() {
+        let none = dfa(r"a+").intersection(&dfa(r"b+"));
+        assert!(!accepts(&none, ""));
+        assert!(!accepts(&none, "a"));
+        assert!(none.live_states().is_empty());
+    }
+
+    #[test]
+    fn complement() {
+        let not_ab = dfa(r"ab").complement();
+        assert!(accepts(&not_ab, ""));
+        assert!(accepts(&not_ab, "a"));
+        assert!(!accepts(&not_ab, "ab"));
+        assert!(accepts(&not_ab, "abb"));
+        assert!(accepts(&not_ab, "\u{10FFFF}"));
+    }
+
+    #[test]
+    fn complement_is_complete() {
+        let not_ab = dfa(r"ab").complement();
+        for state in not_ab.states() {
+            let mut covered = IntervalSet::new();
+            for (on, _) in not_ab.transitions_from(&state).unwrap() {
+                covered.insert(*on);
+            }
+            assert!(covered.complement().is_empty());
+        }
+    }
+
+    #[test]
+    fn live_states() {
+        let not_ab = dfa(r"ab").complement();
+        assert_eq!(not_ab.live_states().len(), not_ab.states().count());
+        let ab = not_ab.complement();
+        assert!(accepts(&ab, "ab"));
+        // The first dead state can't lead to acceptance anymore, the second
+        // one is unreachable, but accepting
+        let live = ab.live_states();
+        assert!((0..=2).all(|id| live.contains(&State(id))));
+        assert!(!live.contains(&State(3)));
+        assert!(live.contains(&State(4)));
+    }
+
+    #[test]
+    fn operators_in_regex() {
+        let comment = dfa(r"/\*~(.*\*/.*)\*/");
+        assert!(accepts(&comment, "/**/"));
+        assert!(accepts(&comment, "/* a * b */"));
+        assert!(!accepts(&comment, "/* a */ b */"));
+        let keyword_free = dfa(r"[a-z]+&~(if|else)");
+        assert!(accepts(&keyword_free, "iff"));
+        assert!(!accepts(&keyword_free, "else"));
+    }
+
+    #[test]
+    fn minimize_merges_equivalent_states() {
+        let ab_or_cb = dfa(r"ab|cb");
+        assert_eq!(ab_or_cb.state_count(), 5);
+        let minimal = ab_or_cb.minimize();
+        assert_eq!(minimal.state_count(), 3);
+        assert!(accepts(&minimal, "ab"));
+        assert!(accepts(&minimal, "cb"));
+        assert!(!accepts(&minimal, "b"));
+        assert!(!accepts(&minimal, "abb"));
+    }
+
+    #[test]
+    fn minimize_keeps_accepting_values_apart() {
+        let mut nfa = NFA::new();
+        nfa.add_regex_with_accepting_value(&regex::parse(r"a").unwrap(), 1);
+        nfa.add_regex_with_accepting_value(&regex::parse(r"b").unwrap(), 2);
+        nfa.add_regex_with_accepting_value(&regex::parse(r"c").unwrap(), 2);
+        let minimal = Automaton::from_nfa(nfa, |a, _| a).minimize();
+        assert_eq!(minimal.state_count(), 3);
+        let value = |c| minimal.transitions_from(&minimal.start).and_then(|trs| trs.get(&c)).and_then(|s| minimal.accepting_value(s));
+        assert_eq!((value('a'), value('b'), value('c')), (Some(&1), Some(&2), Some(&2)));
+    }
+
+    #[test]
+    fn minimize_removes_dead_states() {
+        let minimal = dfa(r"ab").complement().complement().minimize();
+        assert_eq!(minimal.state_count(), 3);
+        assert!(accepts(&minimal, "ab"));
+        assert!(!accepts(&minimal, "a"));
+    }
+
+    #[test]
+    fn minimize_keywords() {
+        let keywords = dfa(r"do|end|for|function|if|in|local|repeat|return|then|until|while");
+        let minimal = keywords.minimize();
+        assert!(minimal.state_count() < keywords.state_count());
+        for keyword in &["do", "end", "function", "in", "until", "while"] {
+            assert!(accepts(&minimal, keyword));
+        }
+        assert!(!accepts(&minimal, "fun"));
+        // Every keyword ends in the same accepting state
+        assert_eq!(minimal.states().filter(|s| minimal.is_accepting(s)).count(), 1);
+    }
+
+    #[test]
+    fn dot_export() {
+        let mut nfa = NFA::new();
+        nfa.add_regex_with_accepting_value(&regex::parse(r"[a-z]\n").unwrap(), "Line");
+        let dot = Automaton::from_nfa(nfa, |a, _| a).to_dot(|value| value.to_string());
+        assert_eq!(dot, concat!(
+            "digraph {\n",
+            "    rankdir=LR;\n",
+            "    node [shape=circle];\n",
+            "    start [shape=point];\n",
+            "    start -> 0;\n",
+            "    0;\n",
+            "    1;\n",
+            "    2 [shape=doublecircle, label=\"2\\nLine\"];\n",
+            "    0 -> 1 [label=\"a-z\"];\n",
+            "    1 -> 2 [label=\"\\\\n\"];\n",
+            "}\n",
+        ));
+    }
+
+    #[test]
+    fn step() {
+        let ab = dfa(r"ab");
+        let a = ab.step(ab.start, &'a').unwrap();
+        assert!(ab.step(ab.start, &'b').is_none());
+        assert!(ab.is_accepting(&ab.step(a, &'b').unwrap()));
+    }
+
+    #[test]
+    fn accepts_borrowed_symbols() {
+        let digits = dfa(r"[0-9]+");
+        let input = vec!['4', '2'];
+        assert!(digits.accepts(&input));
+        assert!(digits.accepts(input));
+        assert!(!digits.accepts("".chars()));
+    }
+
+    #[test]
+    fn longest_match() {
+        let mut nfa = NFA::new();
+        nfa.add_regex_with_accepting_value(&regex::parse(r"[a-z]+").unwrap(), "Ident");
+        nfa.add_regex_with_accepting_value(&regex::parse(r"if").unwrap(), "If");
+        let dfa = Automaton::from_nfa(nfa, |_, b| b);
+        assert_eq!(dfa.longest_match("if".chars()), Some((2, &"If")));
+        assert_eq!(dfa.longest_match("iffy x".chars()), Some((4, &"Ident")));
+        assert_eq!(dfa.longest_match("1".chars()), None);
+    }
+
+    #[test]
+    fn longest_match_backtracks() {
+        assert_eq!(dfa(r"a|abc").longest_match("abd".chars()), Some((1, &())));
+        assert_eq!(dfa(r"a*").longest_match("b".chars()), Some((0, &())));
+    }
+
+    #[test]
+    fn symbol_classes() {
+        let dfa = dfa(r"[a-z]+|[0-9]+x");
+        let classes = dfa.symbol_classes();
+        let class = |c| *classes.get(&c).unwrap();
+        assert_eq!(class('\0'), 0);
+        assert_eq!(class('0'), 1);
+        assert_eq!(class('9'), 1);
+        assert_eq!(class(':'), 0);
+        assert_eq!(class('a'), 2);
+        assert_eq!(class('x'), 3);
+        assert_eq!(class('y'), 2);
+        assert_eq!(class('\u{10FFFF}'), 0);
+        assert_eq!(classes.iter().map(|(_, class)| *class).max(), Some(3));
+    }
+
+    #[test]
+    fn utf8_encoding() {
+        let bytes = dfa(r"[a-zé]+|ő\d|[^\0-\u{FFFF}]").to_utf8();
+        let accepts_str = |s: &str| bytes.accepts(s.as_bytes());
+        assert!(accepts_str("abé"));
+        assert!(accepts_str("ő7"));
+        assert!(accepts_str("\u{10000}"));
+        assert!(accepts_str("\u{10FFFF}"));
+        assert!(!accepts_str("ö"));
+        assert!(!accepts_str("ő"));
+        assert!(!accepts_str("\u{FFFF}"));
+        // Truncated and invalid sequences
+        assert!(!bytes.accepts(&[b'a', 0xC3]));
+        assert!(!bytes.accepts(&[0xC3, 0x29]));
+        assert!(!bytes.accepts(&[0xED, 0xA0, 0x80]));
+        assert_eq!(bytes.longest_match("abé!".as_bytes()).map(|(len, _)| len), Some(4));
+    }
+}
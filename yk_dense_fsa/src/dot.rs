@@ -0,0 +1,73 @@
+/**
+ * Helpers for exporting the automata in Graphviz DOT format.
+ */
+
+use std::fmt;
+use yk_intervals::{Discrete, Interval, IntervalSet, LowerBound, UpperBound};
+
+/// Escapes the text so it can be placed between quotes in a DOT file. The
+/// characters show up escaped like in Rust strings, so control characters are
+/// displayed as '\n' instead of breaking the layout.
+pub(crate) fn escape(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '"' => "\\\"".to_string(),
+            '\'' => "'".to_string(),
+            c => c.escape_debug().to_string().replace('\\', "\\\\"),
+        })
+        .collect()
+}
+
+/// Creates an edge label from the intervals, like "0-9, a-z". The adjacent
+/// intervals are merged first.
+pub(crate) fn interval_label<T>(intervals: Vec<Interval<T>>) -> String where T : Discrete + fmt::Display {
+    let mut set: IntervalSet<T> = intervals.into_iter().collect();
+    set.normalize();
+    let parts: Vec<String> = set.iter()
+        .map(|iv| match (&iv.lower, &iv.upper) {
+            (LowerBound::Included(a), UpperBound::Included(b)) if a == b => escape(&a.to_string()),
+            (LowerBound::Included(a), UpperBound::Included(b)) =>
+                format!("{}-{}", escape(&a.to_string()), escape(&b.to_string())),
+            _ => unreachable!("Normalized intervals are closed!"),
+        })
+        .collect();
+    parts.join(", ")
+}
+
+/// Creates the node statement of a state, accepting states get a double
+/// circle and the formatted accepting value under their id.
+pub(crate) fn state_node(id: usize, accepting_value: Option<String>) -> String {
+    match accepting_value {
+        Some(value) if value.is_empty() => format!("    {} [shape=doublecircle];\n", id),
+        Some(value) => format!("    {} [shape=doublecircle, label=\"{}\\n{}\"];\n", id, id, escape(&value)),
+        None => format!("    {};\n", id),
+    }
+}
+
+/// The beginning of the graph, with an arrow pointing to the start state.
+pub(crate) fn header(start: usize) -> String {
+    format!("digraph {{\n    rankdir=LR;\n    node [shape=circle];\n    start [shape=point];\n    start -> {};\n", start)
+}
+
+// Tests ///////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod dot_tests {
+    use super::*;
+
+    fn closed(a: char, b: char) -> Interval<char> {
+        Interval::with_bounds(LowerBound::Included(a), UpperBound::Included(b))
+    }
+
+    #[test]
+    fn escaped_labels() {
+        assert_eq!(escape("a\"b'"), r#"a\"b'"#);
+        assert_eq!(escape("\n\\"), r"\\n\\\\");
+    }
+
+    #[test]
+    fn merged_intervals() {
+        let label = interval_label(vec![closed('a', 'c'), Interval::singleton('_'), closed('d', 'z')]);
+        assert_eq!(label, "_, a-z");
+    }
+}
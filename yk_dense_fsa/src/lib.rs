@@ -4,3 +4,6 @@ pub extern crate yk_regex_parse;
 
 pub mod nfa;
 pub mod dfa;
+pub mod utf8;
+
+mod dot;
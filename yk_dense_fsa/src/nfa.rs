@@ -3,8 +3,11 @@
  */
 
 use std::collections::{BTreeMap, BTreeSet};
-use yk_intervals::{Interval, IntervalMap, IntervalSet, LowerBound, UpperBound};
+use std::fmt;
+use yk_intervals::{Discrete, Interval, IntervalMap, IntervalSet, LowerBound, UpperBound};
 use yk_regex_parse as regex;
+use crate::dfa::Automaton as DFA;
+use crate::dot;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct State(usize);
@@ -109,6 +112,39 @@ impl <T, AcceptingValue> Automaton<T, AcceptingValue> where T : Clone + Ord {
     }
 }
 
+/**
+ * Graphviz export.
+ */
+
+impl <T, AcceptingValue> Automaton<T, AcceptingValue> where T : Discrete + fmt::Display {
+    /// Creates a Graphviz DOT representation of the automaton. The transitions
+    /// between two states are drawn as a single edge, labeled with the
+    /// intervals, epsilon-transitions are dashed. The accepting states are
+    /// labeled with their value, as formatted by the given function.
+    pub fn to_dot<F>(&self, format_value: F) -> String where F : Fn(&AcceptingValue) -> String {
+        let mut result = dot::header(self.start.id());
+        for state in self.states() {
+            result.push_str(&dot::state_node(state.id(), self.accepting_value(&state).map(&format_value)));
+        }
+        for state in self.states() {
+            let mut edges: BTreeMap<usize, Vec<Interval<T>>> = BTreeMap::new();
+            for (on, to) in self.transitions_from(&state).into_iter().flatten() {
+                for to in to {
+                    edges.entry(to.id()).or_default().push(on.clone());
+                }
+            }
+            for (to, intervals) in edges {
+                result.push_str(&format!("    {} -> {} [label=\"{}\"];\n", state.id(), to, dot::interval_label(intervals)));
+            }
+            for to in self.epsilon.get(&state).into_iter().flatten() {
+                result.push_str(&format!("    {} -> {} [label=\"ε\", style=dashed];\n", state.id(), to.id()));
+            }
+        }
+        result.push_str("}\n");
+        result
+    }
+}
+
 /**
  * Thompson's-construction.
  */
@@ -148,9 +184,17 @@ fn thompson_construct<AcceptingValue>(nfa: &mut Automaton<char, AcceptingValue>,
         regex::Node::Alternative{ first, second } =>
             thompson_construct_alternative(nfa, first, second),
 
+        regex::Node::Intersection{ first, second } => {
+            let both = determinize(first).intersection(&determinize(second));
+            embed_dfa(nfa, &both)
+        },
+
         regex::Node::Sequence{ first, second } =>
             thompson_construct_sequence(nfa, first, second),
 
+        regex::Node::Complement{ subnode } =>
+            embed_dfa(nfa, &determinize(subnode).complement()),
+
         regex::Node::Quantified{ subnode, quantifier } =>
             thompson_construct_quantified(nfa, subnode, *quantifier),
 
@@ -162,6 +206,33 @@ fn thompson_construct<AcceptingValue>(nfa: &mut Automaton<char, AcceptingValue>,
     }
 }
 
+// Intersection and complement have no Thompson-construction, they are done on
+// the standalone DFA of the subexpressions
+fn determinize(rx: &regex::Node) -> DFA<char> {
+    Automaton::<char>::from(rx.clone()).into()
+}
+
+// Copies the live part of the DFA into the NFA, every accepting state gets an
+// epsilon-transition to the returned end state
+fn embed_dfa<AcceptingValue>(nfa: &mut Automaton<char, AcceptingValue>, dfa: &DFA<char>) -> (State, State) {
+    let live = dfa.live_states();
+    let states: BTreeMap<_, _> = dfa.states().map(|s| (s.id(), nfa.unique_state())).collect();
+    let end = nfa.unique_state();
+
+    for from in dfa.states().filter(|s| live.contains(s)) {
+        if let Some(trs) = dfa.transitions_from(&from) {
+            for (on, to) in trs.iter().filter(|(_, to)| live.contains(to)) {
+                nfa.add_transition(states[&from.id()], *on, states[&to.id()]);
+            }
+        }
+        if dfa.is_accepting(&from) {
+            nfa.add_epsilon_transition(states[&from.id()], end);
+        }
+    }
+
+    (states[&dfa.start.id()], end)
+}
+
 fn thompson_construct_alternative<AcceptingValue>(nfa: &mut Automaton<char, AcceptingValue>,
     left: &regex::Node, right: &regex::Node) -> (State, State) {
 
@@ -231,22 +302,26 @@ fn thompson_construct_grouping<AcceptingValue>(nfa: &mut Automaton<char, Accepti
     negated: bool, elements: &Vec<regex::GroupingElement>) -> (State, State) {
 
     let mut ivs = IntervalSet::new();
-
     for elem in elements {
         match elem {
-            regex::GroupingElement::Literal(ch) => {
-                ivs.insert(Interval::singleton(*ch));
-            },
-
-            regex::GroupingElement::Range(cfrom, cto) => {
-                ivs.insert(Interval::with_bounds(LowerBound::Included(*cfrom), UpperBound::Included(*cto)));
+            regex::GroupingElement::Literal(ch) =>
+                ivs.insert(Interval::singleton(*ch)),
+
+            regex::GroupingElement::Range(cfrom, cto) =>
+                ivs.insert(closed_interval(*cfrom, *cto)),
+
+            regex::GroupingElement::Property{ negated, property } => {
+                let prop: IntervalSet<char> = property.ranges().iter()
+                    .map(|(cfrom, cto)| closed_interval(*cfrom, *cto))
+                    .collect();
+                let prop = if *negated { prop.complement() } else { prop };
+                ivs = ivs.union(&prop);
             },
         }
     }
 
-    if negated {
-        ivs.invert();
-    }
+    // The complement only contains valid characters, in closed form
+    let ivs = if negated { ivs.complement() } else { ivs };
 
     let start = nfa.unique_state();
     let end = nfa.unique_state();
@@ -258,6 +333,10 @@ fn thompson_construct_grouping<AcceptingValue>(nfa: &mut Automaton<char, Accepti
     (start, end)
 }
 
+fn closed_interval(cfrom: char, cto: char) -> Interval<char> {
+    Interval::with_bounds(LowerBound::Included(cfrom), UpperBound::Included(cto))
+}
+
 fn thompson_construct_literal<AcceptingValue>(nfa: &mut Automaton<char, AcceptingValue>,
     ch: char) -> (State, State) {
 
@@ -285,4 +364,24 @@ fn thompson_construct_repeat<AcceptingValue>(nfa: &mut Automaton<char, Accepting
 }
 
 // TODO: An NFA could be constructed trivially from a DFA (so we could have From<DFA>)
-// implemented here. That could be used for some optimizations.
+// implemented here. That could be used for some optimizations. For now only
+// embed_dfa does something like that.
+
+// Tests ///////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod nfa_tests {
+    use super::*;
+
+    #[test]
+    fn dot_export() {
+        let nfa: Automaton<char> = regex::parse(r"a|b").unwrap().into();
+        let dot = nfa.to_dot(|_| String::new());
+        assert!(dot.starts_with("digraph {\n"));
+        assert!(dot.contains("    start -> 0;\n"));
+        assert!(dot.contains("    2 [shape=doublecircle];\n"));
+        assert!(dot.contains("    0 -> 1 [label=\"ε\", style=dashed];\n"));
+        assert!(dot.contains("    3 -> 4 [label=\"a\"];\n"));
+        assert!(dot.ends_with("}\n"));
+    }
+}
@@ -0,0 +1,133 @@
+/**
+ * Encoding character ranges as UTF-8 byte sequences, so automata over
+ * characters can be turned into ones reading bytes.
+ */
+
+use yk_intervals::{Interval, LowerBound, UpperBound};
+
+// The largest code points encoded with 1, 2 and 3 bytes
+const MAX_SCALARS: [u32; 3] = [0x7F, 0x7FF, 0xFFFF];
+
+/// Splits the inclusive character range into sequences of byte ranges, where
+/// each sequence matches the UTF-8 encoding of a part of the characters. The
+/// sequences match disjoint sets of characters and come in ascending order.
+/// For example 'a'..='\u{FF}' becomes [61-7F] and [C2-C3][80-BF].
+pub fn sequences(lower: char, upper: char) -> Vec<Vec<Interval<u8>>> {
+    let mut result = Vec::new();
+    let mut stack = vec![(lower as u32, upper as u32)];
+    'ranges: while let Some((lower, upper)) = stack.pop() {
+        // The surrogates are not characters, so they have no encoding
+        if lower <= 0xD7FF && upper >= 0xE000 {
+            stack.push((0xE000, upper));
+            stack.push((lower, 0xD7FF));
+            continue;
+        }
+
+        // Both ends must be encoded with the same number of bytes
+        if let Some(max) = MAX_SCALARS.iter().find(|max| lower <= **max && **max < upper) {
+            stack.push((max + 1, upper));
+            stack.push((lower, *max));
+            continue;
+        }
+
+        // The continuation bytes can only be ranges, if the range covers the
+        // whole block below the common leading bits
+        for bits in &[6, 12, 18] {
+            let mask = (1u32 << bits) - 1;
+            if lower & !mask != upper & !mask {
+                if lower & mask != 0 {
+                    stack.push(((lower | mask) + 1, upper));
+                    stack.push((lower, lower | mask));
+                    continue 'ranges;
+                }
+                if upper & mask != mask {
+                    stack.push((upper & !mask, upper));
+                    stack.push((lower, (upper & !mask) - 1));
+                    continue 'ranges;
+                }
+            }
+        }
+
+        let mut lower_bytes = [0; 4];
+        let mut upper_bytes = [0; 4];
+        let lower_bytes = char::from_u32(lower).unwrap().encode_utf8(&mut lower_bytes).as_bytes();
+        let upper_bytes = char::from_u32(upper).unwrap().encode_utf8(&mut upper_bytes).as_bytes();
+        result.push(lower_bytes.iter().zip(upper_bytes)
+            .map(|(a, b)| Interval::with_bounds(LowerBound::Included(*a), UpperBound::Included(*b)))
+            .collect());
+    }
+    result
+}
+
+// Tests ///////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod utf8_tests {
+    use super::*;
+
+    fn bytes(a: u8, b: u8) -> Interval<u8> {
+        Interval::with_bounds(LowerBound::Included(a), UpperBound::Included(b))
+    }
+
+    fn matches(sequence: &[Interval<u8>], encoded: &[u8]) -> bool {
+        sequence.len() == encoded.len() && sequence.iter().zip(encoded).all(|(range, byte)| {
+            match (&range.lower, &range.upper) {
+                (LowerBound::Included(a), UpperBound::Included(b)) => a <= byte && byte <= b,
+                _ => false,
+            }
+        })
+    }
+
+    // Every character in the range must be matched by exactly one sequence,
+    // and the sequences can't match more characters than the range has
+    fn assert_exact(lower: char, upper: char) {
+        let sequences = sequences(lower, upper);
+        let mut matched = 0;
+        for c in lower..=upper {
+            let mut encoded = [0; 4];
+            let encoded = c.encode_utf8(&mut encoded).as_bytes();
+            assert_eq!(sequences.iter().filter(|s| matches(s, encoded)).count(), 1, "{:?}", c);
+            matched += 1;
+        }
+        let matchable: usize = sequences.iter()
+            .map(|s| s.iter().map(|range| match (&range.lower, &range.upper) {
+                (LowerBound::Included(a), UpperBound::Included(b)) => (b - a) as usize + 1,
+                _ => 0,
+            }).product::<usize>())
+            .sum();
+        assert_eq!(matchable, matched);
+    }
+
+    #[test]
+    fn ascii() {
+        assert_eq!(sequences('a', 'z'), vec![vec![bytes(b'a', b'z')]]);
+    }
+
+    #[test]
+    fn multiple_lengths() {
+        assert_eq!(sequences('a', '\u{FF}'), vec![
+            vec![bytes(0x61, 0x7F)],
+            vec![bytes(0xC2, 0xC3), bytes(0x80, 0xBF)],
+        ]);
+        assert_eq!(sequences('\u{80}', '\u{7FF}'), vec![vec![bytes(0xC2, 0xDF), bytes(0x80, 0xBF)]]);
+    }
+
+    #[test]
+    fn exact_ranges() {
+        assert_exact('\0', '\u{7F}');
+        assert_exact('é', 'ő');
+        assert_exact('\u{7F}', '\u{801}');
+        assert_exact('\u{D000}', '\u{E100}');
+        assert_exact('\u{FFF0}', '\u{10123}');
+        assert_exact('\u{10FF00}', '\u{10FFFF}');
+    }
+
+    #[test]
+    fn surrogates_skipped() {
+        let sequences = sequences('\u{D7FF}', '\u{E000}');
+        assert_eq!(sequences, vec![
+            vec![bytes(0xED, 0xED), bytes(0x9F, 0x9F), bytes(0xBF, 0xBF)],
+            vec![bytes(0xEE, 0xEE), bytes(0x80, 0x80), bytes(0x80, 0x80)],
+        ]);
+    }
+}
@@ -3,6 +3,8 @@
  */
 
 use std::cmp::Ordering;
+use crate::discrete::Discrete;
+use crate::shift::Shift;
 
 /// Represents the lower bound of an interval
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -233,3 +235,66 @@ impl <T> UpperBound<T> where T : Clone {
         }
     }
 }
+
+/**
+ * Inclusive forms for discrete domains.
+ */
+
+impl <T> LowerBound<T> where T : Discrete {
+    /// The smallest element satisfying the bound, if there is any.
+    pub fn to_included(&self) -> Option<T> {
+        match self {
+            LowerBound::Unbounded => Some(T::MIN),
+            LowerBound::Excluded(x) => x.successor(),
+            LowerBound::Included(x) => Some(x.clone()),
+        }
+    }
+
+    /// Checks if there's no element between the other bound and this one,
+    /// meaning that the two intervals can be merged.
+    pub fn is_adjacent(&self, other: &UpperBound<T>) -> bool {
+        match (other.to_included(), self.to_included()) {
+            (Some(u), Some(l)) => u.successor() == Some(l),
+            _ => false,
+        }
+    }
+}
+
+impl <T> UpperBound<T> where T : Discrete {
+    /// The largest element satisfying the bound, if there is any.
+    pub fn to_included(&self) -> Option<T> {
+        match self {
+            UpperBound::Unbounded => Some(T::MAX),
+            UpperBound::Excluded(x) => x.predecessor(),
+            UpperBound::Included(x) => Some(x.clone()),
+        }
+    }
+
+    pub fn is_adjacent(&self, other: &LowerBound<T>) -> bool {
+        other.is_adjacent(self)
+    }
+}
+
+/**
+ * Shifting bounds.
+ */
+
+impl <T> LowerBound<T> where T : Shift {
+    pub fn shifted(&self, offset: &T::Offset) -> Self {
+        match self {
+            LowerBound::Unbounded => LowerBound::Unbounded,
+            LowerBound::Excluded(x) => LowerBound::Excluded(x.shift(offset)),
+            LowerBound::Included(x) => LowerBound::Included(x.shift(offset)),
+        }
+    }
+}
+
+impl <T> UpperBound<T> where T : Shift {
+    pub fn shifted(&self, offset: &T::Offset) -> Self {
+        match self {
+            UpperBound::Unbounded => UpperBound::Unbounded,
+            UpperBound::Excluded(x) => UpperBound::Excluded(x.shift(offset)),
+            UpperBound::Included(x) => UpperBound::Included(x.shift(offset)),
+        }
+    }
+}
@@ -0,0 +1,156 @@
+/**
+ * Domains where every element has a well-defined neighbor. For these every
+ * interval can be written in a canonical closed form.
+ */
+
+/// Represents a bounded, discrete domain
+pub trait Discrete : Ord + Clone {
+    /// The smallest element of the domain
+    const MIN: Self;
+    /// The largest element of the domain
+    const MAX: Self;
+
+    /// The next element, if there is any.
+    fn successor(&self) -> Option<Self>;
+
+    /// The previous element, if there is any.
+    fn predecessor(&self) -> Option<Self>;
+
+    /// The number of successor steps from this element to the other one,
+    /// which must not be smaller.
+    fn steps_to(&self, other: &Self) -> u128;
+
+    /// The element the given number of successor steps away, if there is any.
+    fn forward(&self, steps: u128) -> Option<Self>;
+}
+
+/**
+ * Characters, skipping the surrogate range.
+ */
+
+impl Discrete for char {
+    const MIN: Self = '\0';
+    const MAX: Self = std::char::MAX;
+
+    fn successor(&self) -> Option<Self> {
+        match *self as u32 {
+            0xD7FF => Some('\u{E000}'),
+            c => std::char::from_u32(c + 1),
+        }
+    }
+
+    fn predecessor(&self) -> Option<Self> {
+        match *self as u32 {
+            0 => None,
+            0xE000 => Some('\u{D7FF}'),
+            c => std::char::from_u32(c - 1),
+        }
+    }
+
+    fn steps_to(&self, other: &Self) -> u128 {
+        assert!(self <= other);
+        let (from, to) = (*self as u32, *other as u32);
+        let gap = if from < 0xD800 && to > 0xDFFF { 0x800 } else { 0 };
+        (to - from - gap) as u128
+    }
+
+    fn forward(&self, steps: u128) -> Option<Self> {
+        if steps > self.steps_to(&Self::MAX) {
+            return None;
+        }
+        let from = *self as u32;
+        let to = from + steps as u32;
+        let gap = if from < 0xD800 && to >= 0xD800 { 0x800 } else { 0 };
+        std::char::from_u32(to + gap)
+    }
+}
+
+/**
+ * Integers.
+ */
+
+macro_rules! impl_discrete_for_integers {
+    ( $( $t:ty ),* ) => {
+        $(
+            impl Discrete for $t {
+                const MIN: Self = <$t>::MIN;
+                const MAX: Self = <$t>::MAX;
+
+                fn successor(&self) -> Option<Self> {
+                    self.checked_add(1)
+                }
+
+                fn predecessor(&self) -> Option<Self> {
+                    self.checked_sub(1)
+                }
+
+                fn steps_to(&self, other: &Self) -> u128 {
+                    assert!(self <= other);
+                    // Two's complement makes the wrapping difference right for signed types too
+                    (*other as u128).wrapping_sub(*self as u128)
+                }
+
+                fn forward(&self, steps: u128) -> Option<Self> {
+                    if steps > self.steps_to(&Self::MAX) {
+                        None
+                    }
+                    else {
+                        Some((*self as u128).wrapping_add(steps) as $t)
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_discrete_for_integers!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize);
+
+// Tests ///////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod discrete_tests {
+    use super::*;
+
+    #[test]
+    fn char_neighbors() {
+        assert_eq!('a'.successor(), Some('b'));
+        assert_eq!('b'.predecessor(), Some('a'));
+        assert_eq!('\0'.predecessor(), None);
+        assert_eq!(std::char::MAX.successor(), None);
+    }
+
+    #[test]
+    fn char_skips_surrogates() {
+        assert_eq!('\u{D7FF}'.successor(), Some('\u{E000}'));
+        assert_eq!('\u{E000}'.predecessor(), Some('\u{D7FF}'));
+    }
+
+    #[test]
+    fn integer_neighbors() {
+        assert_eq!(3u8.successor(), Some(4));
+        assert_eq!(255u8.successor(), None);
+        assert_eq!(0u8.predecessor(), None);
+        assert_eq!((-128i8).predecessor(), None);
+        assert_eq!(0i32.predecessor(), Some(-1));
+    }
+
+    #[test]
+    fn char_steps() {
+        assert_eq!('a'.steps_to(&'z'), 25);
+        assert_eq!('\u{D7FF}'.steps_to(&'\u{E000}'), 1);
+        assert_eq!('\0'.steps_to(&std::char::MAX), 0x10FFFF - 0x800);
+        assert_eq!('a'.forward(25), Some('z'));
+        assert_eq!('\u{D7FE}'.forward(2), Some('\u{E000}'));
+        assert_eq!(std::char::MAX.forward(1), None);
+    }
+
+    #[test]
+    fn integer_steps() {
+        assert_eq!((-128i8).steps_to(&127), 255);
+        assert_eq!(3u8.steps_to(&3), 0);
+        assert_eq!(0u128.steps_to(&u128::MAX), u128::MAX);
+        assert_eq!((-128i8).forward(255), Some(127));
+        assert_eq!((-128i8).forward(256), None);
+        assert_eq!(250u8.forward(6), None);
+    }
+}
@@ -4,6 +4,8 @@
 
 //use std::convert::{From};
 use crate::bound::{LowerBound, UpperBound};
+use crate::discrete::Discrete;
+use crate::shift::Shift;
 
 /// Represents a generic interval
 #[derive(Clone, Copy, PartialEq, Eq)]
@@ -151,11 +153,52 @@ impl <T> Interval<T> where T : PartialOrd {
             | (LowerBound::Excluded(x), UpperBound::Included(y))
             | (LowerBound::Excluded(x), UpperBound::Excluded(y)) => x >= y,
 
+            (LowerBound::Included(x), UpperBound::Included(y)) => x > y,
+
             _ => false,
         }
     }
 }
 
+impl <T> Interval<T> where T : Discrete {
+    /// Returns the canonical form of the interval, where both bounds are
+    /// included, or None if the interval is empty.
+    pub fn to_closed(&self) -> Option<Self> {
+        let lower = self.lower.to_included()?;
+        let upper = self.upper.to_included()?;
+        if lower > upper {
+            None
+        }
+        else {
+            Some(Interval::with_bounds(LowerBound::Included(lower), UpperBound::Included(upper)))
+        }
+    }
+
+    /// Checks if the interval is empty, knowing that there's nothing between
+    /// neighbouring elements. Unlike 'is_empty', this considers ('o'; 'p')
+    /// empty.
+    pub fn is_empty_discrete(&self) -> bool {
+        self.to_closed().is_none()
+    }
+}
+
+impl <T> Interval<T> where T : Shift {
+    /// Returns the interval with both of its bounds moved by the offset.
+    pub fn shifted(&self, offset: &T::Offset) -> Self {
+        Interval::with_bounds(self.lower.shifted(offset), self.upper.shifted(offset))
+    }
+}
+
+impl <T> Interval<T> where T : Ord {
+    /// Checks if the interval starts at or after the given point.
+    pub fn starts_at_or_after(&self, point: &T) -> bool {
+        match &self.lower {
+            LowerBound::Unbounded => false,
+            LowerBound::Excluded(x) | LowerBound::Included(x) => x >= point,
+        }
+    }
+}
+
 /**
  * Relation of intervals.
  */
@@ -171,6 +214,25 @@ impl <T> Interval<T> where T : Ord {
     pub fn is_disjunct(&self, other: &Self) -> bool {
         self.is_before(other) || other.is_before(self)
     }
+
+    /// Checks if the other interval is completely inside this one.
+    pub fn contains_interval(&self, other: &Self) -> bool {
+        self.lower <= other.lower && other.upper <= self.upper
+    }
+}
+
+impl <T> Interval<T> where T : Ord + Clone {
+    /// Returns the common part of the two intervals, if there is any.
+    pub fn intersection(&self, other: &Self) -> Option<Self> {
+        if self.is_disjunct(other) {
+            None
+        }
+        else {
+            let lower = if self.lower < other.lower { &other.lower } else { &self.lower };
+            let upper = if self.upper < other.upper { &self.upper } else { &other.upper };
+            Some(Interval::with_bounds(lower.clone(), upper.clone()))
+        }
+    }
 }
 
 impl <T> Interval<T> where T : Ord + Clone {
@@ -283,6 +345,28 @@ pub(crate) fn intersecting_index_range<'a, T, K, F>(
     from..to
 }
 
+pub(crate) fn element_index<T, K, F>(
+    slice: &[T], element: &K, mut proj: F) -> Result<usize, usize>
+    where F : FnMut(&T) -> &Interval<K>, K : Ord {
+
+    slice.binary_search_by(|x| {
+        let iv = proj(x);
+        if iv.contains(element) {
+            std::cmp::Ordering::Equal
+        }
+        else {
+            let after_lower = match &iv.lower {
+                LowerBound::Unbounded => true,
+                LowerBound::Excluded(x) => x < element,
+                LowerBound::Included(x) => x <= element,
+            };
+            // If the element is past the lower bound, it must be past the
+            // upper bound too, so the interval comes before the element
+            if after_lower { std::cmp::Ordering::Less } else { std::cmp::Ordering::Greater }
+        }
+    })
+}
+
 pub(crate) fn touching_index_range<'a, T, K, F>(
     slice: &'a [T], interval: &'a Interval<K>, mut proj: F) -> std::ops::Range<usize>
     where F : FnMut(&'a T) -> &'a Interval<K> + Copy, K : Ord {
@@ -459,6 +543,37 @@ mod interval_tests {
         );
     }
 
+    /**
+     * Discrete form tests.
+     */
+
+    #[test]
+    fn closed_form() {
+        assert_eq!(
+            Interval::with_bounds(LowerBound::Excluded('a'), UpperBound::Excluded('z')).to_closed(),
+            Some(ri('b'..='y'))
+        );
+        assert_eq!(ri(..3u8).to_closed(), Some(ri(0..=2)));
+        assert_eq!(ri(250u8..).to_closed(), Some(ri(250..=255)));
+    }
+
+    #[test]
+    fn closed_form_empty() {
+        assert_eq!(Interval::with_bounds(LowerBound::Excluded('o'), UpperBound::Excluded('p')).to_closed(), None);
+        assert_eq!(ri(..0u8).to_closed(), None);
+        assert_eq!(ri(5..5).to_closed(), None);
+    }
+
+    #[test]
+    fn discrete_emptiness() {
+        let between_neighbours = Interval::with_bounds(LowerBound::Excluded('o'), UpperBound::Excluded('p'));
+        assert!(!between_neighbours.is_empty());
+        assert!(between_neighbours.is_empty_discrete());
+        assert!(!Interval::with_bounds(LowerBound::Excluded('o'), UpperBound::Excluded('q')).is_empty_discrete());
+        assert!(ri(..0u8).is_empty_discrete());
+        assert!(!ri(255u8..).is_empty_discrete());
+    }
+
     /**
      * The "search" algorithm tests.
      */
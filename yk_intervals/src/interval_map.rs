@@ -3,8 +3,10 @@
  * disjunct but there is a possibility they will touch.
  */
 
-use crate::interval::{Interval, IntervalRelation, intersecting_index_range};
+use crate::interval::{Interval, IntervalRelation, element_index, intersecting_index_range, touching_index_range};
 use crate::bound::{LowerBound, UpperBound};
+use crate::discrete::Discrete;
+use crate::shift::{Shift, Splice, SplicePolicy};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct IntervalMap<K, V> {
@@ -15,6 +17,66 @@ impl <K, V> IntervalMap<K, V> {
     pub fn new() -> Self {
         IntervalMap{ intervals: Vec::new() }
     }
+
+    /// Checks if the map contains no entries at all.
+    pub fn is_empty(&self) -> bool {
+        self.intervals.is_empty()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, (Interval<K>, V)> {
+        self.intervals.iter()
+    }
+}
+
+/**
+ * Lookup.
+ */
+
+impl <K, V> IntervalMap<K, V> where K : Ord {
+    /// Returns the value associated with the interval containing the key.
+    pub fn get(&self, key: &K) -> Option<&V> {
+        match element_index(&self.intervals, key, |x| &x.0) {
+            Ok(idx) => Some(&self.intervals[idx].1),
+            Err(_) => None,
+        }
+    }
+
+    /// Iterates over the entries that have a common element with the given
+    /// interval, in order.
+    pub fn overlapping(&self, interval: &Interval<K>) -> std::slice::Iter<'_, (Interval<K>, V)> {
+        let range = intersecting_index_range(&self.intervals, interval, |x| &x.0);
+        self.intervals[range].iter()
+    }
+}
+
+impl <K, V> IntervalMap<K, V> where K : Ord + Clone, V : Clone {
+    /// Removes the given interval from the map. Entries that are only partially
+    /// covered get clipped, keeping their parts outside of the removed interval.
+    pub fn remove(&mut self, key: Interval<K>) {
+        let range = intersecting_index_range(&self.intervals, &key, |x| &x.0);
+        if range.is_empty() {
+            return;
+        }
+
+        // Only the first and last entries can stick out of the removed interval
+        let mut remaining = Vec::new();
+        if let Some(upper) = key.lower.touching() {
+            let (first_iv, first_val) = &self.intervals[range.start];
+            let piece = Interval::with_bounds(first_iv.lower.clone(), upper);
+            if !piece.is_empty() {
+                remaining.push((piece, first_val.clone()));
+            }
+        }
+        if let Some(lower) = key.upper.touching() {
+            let (last_iv, last_val) = &self.intervals[range.end - 1];
+            let piece = Interval::with_bounds(lower, last_iv.upper.clone());
+            if !piece.is_empty() {
+                remaining.push((piece, last_val.clone()));
+            }
+        }
+
+        self.intervals.splice(range, remaining);
+    }
 }
 
 pub struct Unification<V> {
@@ -327,6 +389,118 @@ impl <K, V> IntervalMap<K, V> where K : Clone + Ord, V : Clone {
     }
 }
 
+/**
+ * Coalescing.
+ */
+
+impl <K, V> IntervalMap<K, V> where K : PartialEq, V : PartialEq {
+    /// Merges every touching pair of entries that have equal values.
+    pub fn coalesce(&mut self) {
+        self.coalesce_range(0..self.intervals.len());
+    }
+
+    fn coalesce_range(&mut self, range: std::ops::Range<usize>) {
+        if range.len() < 2 {
+            return;
+        }
+
+        // We compact the entries into the beginning of the range
+        let mut last = range.start;
+        for idx in (range.start + 1)..range.end {
+            let (before, after) = self.intervals.split_at_mut(idx);
+            let prev = &mut before[last];
+            let curr = &mut after[0];
+            if prev.0.upper.is_touching(&curr.0.lower) && prev.1 == curr.1 {
+                // Extend the previous entry, the current one will be dropped
+                std::mem::swap(&mut prev.0.upper, &mut curr.0.upper);
+            }
+            else {
+                last += 1;
+                self.intervals.swap(last, idx);
+            }
+        }
+        self.intervals.drain((last + 1)..range.end);
+    }
+}
+
+impl <K, V> IntervalMap<K, V> where K : Clone + Ord, V : Clone + PartialEq {
+    /// Same as insert_and_unify, but merges the touched entries with their
+    /// neighbors when they have equal values. Inserting only this way keeps
+    /// the map coalesced.
+    pub fn insert_and_coalesce<F>(&mut self, key: Interval<K>, value: V, unify: F)
+        where F : FnMut(Unification<V>) -> V + Clone {
+
+        self.insert_and_unify(key.clone(), value, unify);
+        let range = touching_index_range(&self.intervals, &key, |x| &x.0);
+        self.coalesce_range(range);
+    }
+}
+
+/**
+ * Normalization for discrete domains.
+ */
+
+impl <K, V> IntervalMap<K, V> where K : Discrete, V : PartialEq {
+    /// Converts every key to closed form, dropping the empty ones and merging
+    /// the entries with equal values that have no element between them.
+    pub fn normalize(&mut self) {
+        let mut result: Vec<(Interval<K>, V)> = Vec::with_capacity(self.intervals.len());
+        for (iv, v) in self.intervals.drain(..) {
+            if let Some(iv) = iv.to_closed() {
+                if let Some(last) = result.last_mut() {
+                    if last.1 == v && last.0.upper.is_adjacent(&iv.lower) {
+                        last.0.upper = iv.upper;
+                        continue;
+                    }
+                }
+                result.push((iv, v));
+            }
+        }
+        self.intervals = result;
+    }
+}
+
+/**
+ * Editing, for maps keyed by positions in a text.
+ */
+
+impl <K, V> IntervalMap<K, V> where K : Shift + Ord + Clone {
+    /// Moves every entry starting at or after the given point by the offset.
+    /// Panics if an entry would be moved onto one that stays in place.
+    pub fn shift_after(&mut self, point: &K, offset: &K::Offset) {
+        let idx = self.intervals.partition_point(|(iv, _)| !iv.starts_at_or_after(point));
+        // Checked before moving anything, so the map stays intact on a panic
+        if idx > 0 && idx < self.intervals.len() {
+            assert!(self.intervals[idx - 1].0.is_before(&self.intervals[idx].0.shifted(offset)),
+                "Shifted entries overlap the ones before them!");
+        }
+        for (iv, _) in &mut self.intervals[idx..] {
+            *iv = iv.shifted(offset);
+        }
+    }
+}
+
+impl <K, V> IntervalMap<K, V> where K : Shift + Ord + Clone, V : Clone {
+    /// Replaces the erased region with inserted content of the given length,
+    /// moving everything after it. The erased region consists of the elements
+    /// of the interval, so `a..=b` erases the same as `a..b+1`. Entries
+    /// intersecting it are handled according to the policy.
+    pub fn splice(&mut self, erased: &Interval<K>, inserted: &K::Offset, policy: SplicePolicy)
+        where K : Discrete {
+        let splice = Splice::new(erased, inserted, policy);
+        for (iv, value) in std::mem::take(&mut self.intervals) {
+            match splice.pieces(&iv) {
+                (Some(left), Some(right)) => {
+                    self.intervals.push((left, value.clone()));
+                    self.intervals.push((right, value));
+                },
+                (Some(piece), None) | (None, Some(piece)) => self.intervals.push((piece, value)),
+                (None, None) => {},
+            }
+        }
+    }
+}
+
 /**
  * Iteration.
  */
@@ -572,4 +746,164 @@ mod interval_map_tests {
         map.insert_and_unify(ri(6..10), vec![2], test_unify);
         assert_eq!(map, ivmap![1..3 => vec![1], 5..6 => vec![1], 6..7 => vec![1, 2], 7..9 => vec![2], 9..10 => vec![1, 2], 10..12 => vec![1], 14..15 => vec![1]]);
     }
+
+    /**
+     * Lookup tests.
+     */
+
+    #[test]
+    fn get_from_map() {
+        let map = ivmap_raw![..2 => 0, 5..7 => 1, 7..=9 => 2, 12.. => 3];
+        assert_eq!(map.get(&1), Some(&0));
+        assert_eq!(map.get(&2), None);
+        assert_eq!(map.get(&5), Some(&1));
+        assert_eq!(map.get(&7), Some(&2));
+        assert_eq!(map.get(&9), Some(&2));
+        assert_eq!(map.get(&10), None);
+        assert_eq!(map.get(&1000), Some(&3));
+        assert_eq!(IntervalMap::<usize, usize>::new().get(&3), None);
+    }
+
+    #[test]
+    fn overlapping_entries() {
+        let map = ivmap_raw![1..3 => 0, 5..7 => 1, 7..9 => 2, 12..15 => 3];
+        let found: Vec<_> = map.overlapping(&ri(6..13)).cloned().collect();
+        assert_eq!(found, vec![(ri(5..7), 1), (ri(7..9), 2), (ri(12..15), 3)]);
+        assert_eq!(map.overlapping(&ri(3..5)).count(), 0);
+        assert_eq!(map.overlapping(&ri(..)).count(), 4);
+    }
+
+    /**
+     * Removal tests.
+     */
+
+    #[test]
+    fn remove_nothing() {
+        let mut map = ivmap_raw![1..3 => 0, 5..7 => 1];
+        map.remove(ri(3..5));
+        assert_eq!(map, ivmap_raw![1..3 => 0, 5..7 => 1]);
+    }
+
+    #[test]
+    fn remove_splits_entry() {
+        let mut map = ivmap_raw![1..10 => 0];
+        map.remove(ri(4..6));
+        assert_eq!(map, ivmap_raw![1..4 => 0, 6..10 => 0]);
+    }
+
+    #[test]
+    fn remove_clips_and_erases() {
+        let mut map = ivmap_raw![1..4 => 0, 5..7 => 1, 7..9 => 2, 10..15 => 3];
+        map.remove(ri(3..12));
+        assert_eq!(map, ivmap_raw![1..3 => 0, 12..15 => 3]);
+    }
+
+    #[test]
+    fn remove_unbounded() {
+        let mut map = ivmap_raw![1..4 => 0, 5..7 => 1, 10..15 => 3];
+        map.remove(ri(6..));
+        assert_eq!(map, ivmap_raw![1..4 => 0, 5..6 => 1]);
+        map.remove(ri(..));
+        assert!(map.is_empty());
+    }
+
+    /**
+     * Coalescing tests.
+     */
+
+    #[test]
+    fn coalesce_touching_equal() {
+        let mut map = ivmap_raw![1..3 => 0, 3..5 => 0, 5..=7 => 0, 9..12 => 0];
+        map.coalesce();
+        assert_eq!(map, ivmap_raw![1..=7 => 0, 9..12 => 0]);
+    }
+
+    #[test]
+    fn coalesce_keeps_different_values() {
+        let mut map = ivmap_raw![1..3 => 0, 3..5 => 1, 5..7 => 1, 7..9 => 0];
+        map.coalesce();
+        assert_eq!(map, ivmap_raw![1..3 => 0, 3..7 => 1, 7..9 => 0]);
+    }
+
+    #[test]
+    fn coalesce_unbounded() {
+        let mut map = ivmap_raw![..3 => 0, 3..5 => 0, 5.. => 0];
+        map.coalesce();
+        assert_eq!(map, ivmap_raw![.. => 0]);
+    }
+
+    #[test]
+    fn insert_and_coalesce_between() {
+        let mut map = ivmap_raw![1..3 => vec![1], 5..7 => vec![1], 9..11 => vec![2]];
+        map.insert_and_coalesce(ri(3..5), vec![1], test_unify);
+        assert_eq!(map, ivmap_raw![1..7 => vec![1], 9..11 => vec![2]]);
+    }
+
+    #[test]
+    fn insert_and_coalesce_split_pieces() {
+        let mut map = ivmap_raw![1..3 => 0, 5..7 => 0, 9..11 => 1];
+        map.insert_and_coalesce(ri(2..6), 0, |u: Unification<i32>| u.existing);
+        assert_eq!(map, ivmap_raw![1..7 => 0, 9..11 => 1]);
+    }
+
+    /**
+     * Normalization tests.
+     */
+
+    #[test]
+    fn normalize_transitions() {
+        let mut map = ivmap_raw!['a'..'f' => 0, 'f'..='k' => 0, 'l'..='m' => 0, 'n'..='z' => 1];
+        map.normalize();
+        assert_eq!(map, ivmap_raw!['a'..='m' => 0, 'n'..='z' => 1]);
+    }
+
+    #[test]
+    fn normalize_drops_empty_keys() {
+        let mut map = IntervalMap{ intervals: vec![
+            (Interval::with_bounds(LowerBound::Excluded(3), UpperBound::Excluded(4)), 0),
+            (ri(4..6), 0),
+        ] };
+        map.normalize();
+        assert_eq!(map, ivmap_raw![4..=5 => 0]);
+    }
+
+    /**
+     * Editing tests.
+     */
+
+    #[test]
+    fn shift_after_point() {
+        let mut map = ivmap_raw![1..3 => 0, 4..6 => 1, 7..9 => 2];
+        map.shift_after(&4, &-1);
+        assert_eq!(map, ivmap_raw![1..3 => 0, 3..5 => 1, 6..8 => 2]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn shift_after_onto_unmoved() {
+        let mut map = ivmap_raw![1..3 => 0, 3..5 => 1];
+        map.shift_after(&3, &-1);
+    }
+
+    #[test]
+    fn shift_after_onto_unmoved_keeps_map() {
+        let mut map = ivmap_raw![1..3 => 0, 3..5 => 1, 6..8 => 2];
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| map.shift_after(&3, &-1)));
+        assert!(result.is_err());
+        assert_eq!(map, ivmap_raw![1..3 => 0, 3..5 => 1, 6..8 => 2]);
+    }
+
+    #[test]
+    fn splice_drop() {
+        let mut map = ivmap_raw![1..3 => 0, 3..6 => 1, 6..9 => 2];
+        map.splice(&ri(4..5), &3, SplicePolicy::Drop);
+        assert_eq!(map, ivmap_raw![1..3 => 0, 8..11 => 2]);
+    }
+
+    #[test]
+    fn splice_clip() {
+        let mut map = ivmap_raw![1..3 => 0, 3..6 => 1, 6..9 => 2];
+        map.splice(&ri(4..7), &0, SplicePolicy::Clip);
+        assert_eq!(map, ivmap_raw![1..3 => 0, 3..4 => 1, 4..6 => 2]);
+    }
 }
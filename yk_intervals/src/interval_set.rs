@@ -3,8 +3,11 @@
  * possible.
  */
 
+use std::iter::FromIterator;
 use crate::bound::{LowerBound, UpperBound};
-use crate::interval::{Interval, touching_index_range};
+use crate::discrete::Discrete;
+use crate::interval::{Interval, element_index, intersecting_index_range, touching_index_range};
+use crate::shift::{Shift, Splice, SplicePolicy};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct IntervalSet<T> {
@@ -15,6 +18,15 @@ impl <T> IntervalSet<T> {
     pub fn new() -> Self {
         IntervalSet{ intervals: Vec::new() }
     }
+
+    /// Checks if the set contains no intervals at all.
+    pub fn is_empty(&self) -> bool {
+        self.intervals.is_empty()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Interval<T>> {
+        self.intervals.iter()
+    }
 }
 
 impl <T> IntervalSet<T> where T : Ord {
@@ -69,6 +81,255 @@ impl <T> IntervalSet<T> where T : Ord {
     }
 }
 
+/**
+ * Queries.
+ */
+
+impl <T> IntervalSet<T> where T : Ord {
+    /// Checks if an element is inside any of the intervals of the set.
+    pub fn contains(&self, element: &T) -> bool {
+        element_index(&self.intervals, element, |x| x).is_ok()
+    }
+
+    /// Checks if every element of this set is inside the other set.
+    pub fn is_subset(&self, other: &Self) -> bool {
+        // Since the other set is unified, each of our intervals must be
+        // covered by exactly one interval of the other set
+        self.intervals.iter().all(|iv| {
+            let range = intersecting_index_range(&other.intervals, iv, |x| x);
+            range.len() == 1 && other.intervals[range.start].contains_interval(iv)
+        })
+    }
+
+    /// Checks if the two sets have any common element.
+    pub fn overlaps(&self, other: &Self) -> bool {
+        let mut i = 0;
+        let mut j = 0;
+        while i < self.intervals.len() && j < other.intervals.len() {
+            let a = &self.intervals[i];
+            let b = &other.intervals[j];
+            if !a.is_disjunct(b) {
+                return true;
+            }
+            if a.upper < b.upper {
+                i += 1;
+            }
+            else {
+                j += 1;
+            }
+        }
+        false
+    }
+}
+
+/**
+ * Set operations.
+ */
+
+impl <T> IntervalSet<T> where T : Ord + Clone {
+    /// Creates a new set containing all elements that are in either set.
+    pub fn union(&self, other: &Self) -> Self {
+        let mut result = self.clone();
+        for iv in &other.intervals {
+            result.insert(iv.clone());
+        }
+        result
+    }
+
+    /// Creates a new set containing all elements that are in both sets.
+    pub fn intersection(&self, other: &Self) -> Self {
+        let mut result = Self::new();
+        let mut i = 0;
+        let mut j = 0;
+        while i < self.intervals.len() && j < other.intervals.len() {
+            let a = &self.intervals[i];
+            let b = &other.intervals[j];
+            // The pieces can't touch, as they are all sub-intervals of
+            // non-touching intervals, so we can simply push them
+            if let Some(common) = a.intersection(b) {
+                result.intervals.push(common);
+            }
+            // Step the one that ends first
+            if a.upper < b.upper {
+                i += 1;
+            }
+            else {
+                j += 1;
+            }
+        }
+        result
+    }
+
+    /// Creates a new set containing the elements of this set that are not in
+    /// the other set.
+    pub fn difference(&self, other: &Self) -> Self {
+        let mut result = Self::new();
+        for iv in &self.intervals {
+            let range = intersecting_index_range(&other.intervals, iv, |x| x);
+            // The lower bound of the remaining piece, None if nothing remains
+            let mut lower = Some(iv.lower.clone());
+            for cut in &other.intervals[range] {
+                if let (Some(l), Some(u)) = (lower, cut.lower.touching()) {
+                    let piece = Interval::with_bounds(l, u);
+                    if !piece.is_empty() {
+                        result.intervals.push(piece);
+                    }
+                }
+                lower = cut.upper.touching();
+            }
+            if let Some(l) = lower {
+                let piece = Interval::with_bounds(l, iv.upper.clone());
+                if !piece.is_empty() {
+                    result.intervals.push(piece);
+                }
+            }
+        }
+        result
+    }
+
+    /// Creates a new set containing the elements that are in exactly one of
+    /// the sets.
+    pub fn symmetric_difference(&self, other: &Self) -> Self {
+        let mut result = self.difference(other);
+        // The two differences can touch, so we need to unify them
+        for iv in other.difference(self) {
+            result.insert(iv);
+        }
+        result
+    }
+}
+
+/**
+ * Normalization for discrete domains.
+ */
+
+impl <T> IntervalSet<T> where T : Discrete {
+    /// Converts every interval to closed form, dropping the empty ones and
+    /// merging the ones that have no element between them.
+    pub fn normalize(&mut self) {
+        let mut result: Vec<Interval<T>> = Vec::with_capacity(self.intervals.len());
+        for iv in self.intervals.drain(..) {
+            if let Some(iv) = iv.to_closed() {
+                if let Some(last) = result.last_mut() {
+                    if last.upper.is_adjacent(&iv.lower) {
+                        last.upper = iv.upper;
+                        continue;
+                    }
+                }
+                result.push(iv);
+            }
+        }
+        self.intervals = result;
+    }
+
+    /// Creates the complement of this set relative to the given universe, in
+    /// closed form.
+    pub fn complement_in(&self, universe: &Self) -> Self {
+        let mut result = universe.difference(self);
+        result.normalize();
+        result
+    }
+
+    /// Creates the complement of this set relative to the whole domain, in
+    /// closed form. For characters this is exactly the set of valid Unicode
+    /// scalar values not in this set.
+    pub fn complement(&self) -> Self {
+        let domain = Interval::with_bounds(LowerBound::Included(T::MIN), UpperBound::Included(T::MAX));
+        self.complement_in(&IntervalSet{ intervals: vec![domain] })
+    }
+
+    /// The number of elements in the set. Panics if it's not representable,
+    /// which can only happen for the full 128-bit integer domains.
+    pub fn cardinality(&self) -> u128 {
+        self.intervals.iter()
+            .filter_map(closed_ends)
+            .map(|(l, u)| l.steps_to(&u).checked_add(1))
+            .fold(Some(0u128), |acc, n| acc?.checked_add(n?))
+            .expect("The cardinality is not representable!")
+    }
+
+    /// Iterates over the elements of the set in order.
+    pub fn elements(&self) -> Elements<'_, T> {
+        Elements{ intervals: self.intervals.iter(), current: None }
+    }
+
+    /// Returns the k-th smallest element of the set, if there is any.
+    pub fn nth(&self, mut k: u128) -> Option<T> {
+        for (l, u) in self.intervals.iter().filter_map(closed_ends) {
+            let steps = l.steps_to(&u);
+            if k <= steps {
+                return l.forward(k);
+            }
+            k -= steps + 1;
+        }
+        None
+    }
+}
+
+// The smallest and largest element of an interval, if it's not empty
+fn closed_ends<T>(iv: &Interval<T>) -> Option<(T, T)> where T : Discrete {
+    let lower = iv.lower.to_included()?;
+    let upper = iv.upper.to_included()?;
+    if lower <= upper { Some((lower, upper)) } else { None }
+}
+
+/// Iterates over the elements of a discrete set in order.
+pub struct Elements<'a, T> {
+    intervals: std::slice::Iter<'a, Interval<T>>,
+    // The next element and the last element of the current interval
+    current: Option<(T, T)>,
+}
+
+impl <'a, T> Iterator for Elements<'a, T> where T : Discrete {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((next, last)) = self.current.take() {
+                if next < last {
+                    self.current = next.successor().map(|s| (s, last));
+                }
+                return Some(next);
+            }
+            self.current = closed_ends(self.intervals.next()?);
+        }
+    }
+}
+
+/**
+ * Editing, for sets of positions in a text.
+ */
+
+impl <T> IntervalSet<T> where T : Shift + Ord + Clone {
+    /// Moves every interval starting at or after the given point by the offset.
+    /// Intervals that get moved onto others are unified with them.
+    pub fn shift_after(&mut self, point: &T, offset: &T::Offset) {
+        let idx = self.intervals.partition_point(|iv| !iv.starts_at_or_after(point));
+        let moved: Vec<_> = self.intervals.drain(idx..).collect();
+        for iv in moved {
+            self.insert(iv.shifted(offset));
+        }
+    }
+
+    /// Replaces the erased region with inserted content of the given length,
+    /// moving everything after it. The erased region consists of the elements
+    /// of the interval, so `a..=b` erases the same as `a..b+1`. Intervals
+    /// intersecting it are handled according to the policy.
+    pub fn splice(&mut self, erased: &Interval<T>, inserted: &T::Offset, policy: SplicePolicy)
+        where T : Discrete {
+        let splice = Splice::new(erased, inserted, policy);
+        for iv in std::mem::take(&mut self.intervals) {
+            let (left, right) = splice.pieces(&iv);
+            if let Some(left) = left {
+                self.insert(left);
+            }
+            if let Some(right) = right {
+                self.insert(right);
+            }
+        }
+    }
+}
+
 impl <T> IntervalSet<T> where T : Clone {
     pub fn invert(&mut self) {
         if self.intervals.is_empty() {
@@ -211,6 +472,16 @@ impl <'a, T> IntoIterator for &'a IntervalSet<T> {
     }
 }
 
+impl <T> FromIterator<Interval<T>> for IntervalSet<T> where T : Ord {
+    fn from_iter<I>(iter: I) -> Self where I : IntoIterator<Item = Interval<T>> {
+        let mut set = Self::new();
+        for iv in iter {
+            set.insert(iv);
+        }
+        set
+    }
+}
+
 // Tests ///////////////////////////////////////////////////////////////////////
 
 #[cfg(test)]
@@ -413,4 +684,239 @@ mod interval_set_tests {
         set.invert();
         assert_eq!(set, ivset![2..6, 7..8, 11..14, 16..18, 21..]);
     }
+
+    /**
+     * Query tests.
+     */
+
+    #[test]
+    fn contains_elements() {
+        let set = ivset![..2, 5..7, 9..=11, 14..];
+        assert!(set.contains(&0));
+        assert!(!set.contains(&2));
+        assert!(!set.contains(&4));
+        assert!(set.contains(&5));
+        assert!(set.contains(&6));
+        assert!(!set.contains(&7));
+        assert!(set.contains(&11));
+        assert!(!set.contains(&13));
+        assert!(set.contains(&100));
+    }
+
+    #[test]
+    fn contains_in_empty() {
+        assert!(!IntervalSet::<usize>::new().contains(&3));
+    }
+
+    #[test]
+    fn subset_of_covering() {
+        assert!(ivset![3..5, 7..9].is_subset(&ivset![1..10]));
+        assert!(ivset![3..5, 7..9].is_subset(&ivset![3..5, 7..9]));
+        assert!(IntervalSet::new().is_subset(&ivset![3..5]));
+    }
+
+    #[test]
+    fn not_subset_when_spanning_gap() {
+        assert!(!ivset![3..8].is_subset(&ivset![1..5, 5..=5, 6..10]));
+        assert!(!ivset![3..5].is_subset(&ivset![4..10]));
+        assert!(!ivset![3..5].is_subset(&IntervalSet::new()));
+    }
+
+    #[test]
+    fn overlapping_sets() {
+        assert!(ivset![1..3, 8..10].overlaps(&ivset![4..6, 9..12]));
+        assert!(!ivset![1..3, 8..10].overlaps(&ivset![3..8, 10..12]));
+        assert!(!ivset![1..3].overlaps(&IntervalSet::new()));
+    }
+
+    /**
+     * Set operation tests.
+     */
+
+    #[test]
+    fn union_disjunct_and_touching() {
+        assert_eq!(ivset![1..3, 8..10].union(&ivset![3..5, 12..14]), ivset![1..5, 8..10, 12..14]);
+    }
+
+    #[test]
+    fn intersection_many() {
+        assert_eq!(
+            ivset![1..5, 7..12, 15..20].intersection(&ivset![3..8, 10..16, 19..]),
+            ivset![3..5, 7..8, 10..12, 15..16, 19..20]
+        );
+    }
+
+    #[test]
+    fn intersection_touching_is_empty() {
+        assert_eq!(ivset![1..3].intersection(&ivset![3..5]), IntervalSet::new());
+    }
+
+    #[test]
+    fn difference_cuts_holes() {
+        assert_eq!(ivset![1..20].difference(&ivset![3..5, 8..10]), ivset![1..3, 5..8, 10..20]);
+    }
+
+    #[test]
+    fn difference_unbounded() {
+        assert_eq!(ivset![..].difference(&ivset![..3, 5..]), ivset![3..5]);
+        assert_eq!(ivset![..10].difference(&ivset![..]), IntervalSet::new());
+    }
+
+    #[test]
+    fn difference_same_start() {
+        assert_eq!(ivset![1..10].difference(&ivset![1..4]), ivset![4..10]);
+    }
+
+    #[test]
+    fn symmetric_difference_unifies() {
+        assert_eq!(ivset![1..5].symmetric_difference(&ivset![5..8]), ivset![1..8]);
+        assert_eq!(ivset![1..5].symmetric_difference(&ivset![3..8]), ivset![1..3, 5..8]);
+    }
+
+    /**
+     * Normalization tests.
+     */
+
+    #[test]
+    fn normalize_to_closed() {
+        let mut set = ivset![3..5, 7..=9];
+        set.normalize();
+        assert_eq!(set, ivset_raw![3..=4, 7..=9]);
+    }
+
+    #[test]
+    fn normalize_merges_adjacent() {
+        let mut set = ivset![..3u8, 3..=5, 6..8, 9..];
+        set.normalize();
+        assert_eq!(set, ivset_raw![0..=7, 9..=255]);
+    }
+
+    #[test]
+    fn normalize_drops_empty() {
+        let mut set = IntervalSet{ intervals: vec![
+            Interval::with_bounds(LowerBound::Excluded('o'), UpperBound::Excluded('p')),
+            ri('x'..='z'),
+        ] };
+        set.normalize();
+        assert_eq!(set, ivset_raw!['x'..='z']);
+    }
+
+    #[test]
+    fn normalize_chars_around_surrogates() {
+        let mut set = ivset!['a'..='\u{D7FF}', '\u{E000}'..];
+        set.normalize();
+        assert_eq!(set, ivset_raw!['a'..=std::char::MAX]);
+    }
+
+    /**
+     * Complement tests.
+     */
+
+    #[test]
+    fn complement_chars() {
+        assert_eq!(ivset!['a'..='z'].complement(), ivset_raw!['\0'..='`', '{'..=std::char::MAX]);
+    }
+
+    #[test]
+    fn complement_around_surrogates() {
+        assert_eq!(
+            ivset!['\u{E000}'..='\u{F000}'].complement(),
+            ivset_raw!['\0'..='\u{D7FF}', '\u{F001}'..=std::char::MAX]
+        );
+    }
+
+    #[test]
+    fn complement_empty_and_full() {
+        assert_eq!(IntervalSet::<u8>::new().complement(), ivset_raw![0..=255]);
+        assert_eq!(ivset![..].complement(), IntervalSet::<u8>::new());
+    }
+
+    #[test]
+    fn complement_in_universe() {
+        assert_eq!(ivset![3u8..5, 8..=9].complement_in(&ivset![0..=10]), ivset_raw![0..=2, 5..=7, 10..=10]);
+    }
+
+    /**
+     * Editing tests.
+     */
+
+    #[test]
+    fn shift_after_point() {
+        let mut set = ivset![1..3, 5..7, 9..12];
+        set.shift_after(&5, &2);
+        assert_eq!(set, ivset_raw![1..3, 7..9, 11..14]);
+    }
+
+    #[test]
+    fn shift_after_unifies_moved_onto() {
+        let mut set = ivset![1..3, 5..7, 9..12];
+        set.shift_after(&5, &-2);
+        assert_eq!(set, ivset_raw![1..5, 7..10]);
+    }
+
+    #[test]
+    fn splice_drop() {
+        let mut set = ivset![1..3, 4..6, 7..9, 10..12];
+        set.splice(&ri(5..8), &1, SplicePolicy::Drop);
+        assert_eq!(set, ivset_raw![1..3, 8..10]);
+    }
+
+    #[test]
+    fn splice_clip() {
+        let mut set = ivset![1..3, 4..6, 7..9, 10..12];
+        set.splice(&ri(5..8), &1, SplicePolicy::Clip);
+        assert_eq!(set, ivset_raw![1..3, 4..5, 6..7, 8..10]);
+    }
+
+    #[test]
+    fn splice_insertion_inside() {
+        let mut set = ivset![1..5];
+        set.splice(&ri(3..3), &2, SplicePolicy::Clip);
+        assert_eq!(set, ivset_raw![1..3, 5..7]);
+        set.splice(&ri(3..5), &0, SplicePolicy::Clip);
+        assert_eq!(set, ivset_raw![1..5]);
+    }
+
+    #[test]
+    fn splice_inclusive_end() {
+        for policy in [SplicePolicy::Drop, SplicePolicy::Clip].iter().cloned() {
+            let mut half_open = ivset![1..3, 5..7, 8..10];
+            let mut inclusive = half_open.clone();
+            half_open.splice(&ri(4..8), &2, policy);
+            inclusive.splice(&ri(4..=7), &2, policy);
+            assert_eq!(half_open, inclusive);
+        }
+        let mut set = ivset![8..10];
+        set.splice(&ri(4..=7), &0, SplicePolicy::Drop);
+        assert_eq!(set, ivset_raw![4..6]);
+    }
+
+    /**
+     * Element tests.
+     */
+
+    #[test]
+    fn cardinality() {
+        assert_eq!(ivset!['a'..='z', '0'..'5'].cardinality(), 31);
+        let full: IntervalSet<u8> = ivset![..];
+        assert_eq!(full.cardinality(), 256);
+        assert_eq!(IntervalSet::<u8>::new().cardinality(), 0);
+        assert_eq!(ivset!['\u{D000}'..='\u{F000}'].cardinality(), 0x2001 - 0x800);
+    }
+
+    #[test]
+    fn elements_in_order() {
+        let set = ivset!['x'..='z', 'a'..'c', (std::ops::Bound::Excluded('e'), std::ops::Bound::Included('f'))];
+        assert_eq!(set.elements().collect::<String>(), "abfxyz");
+        assert_eq!(ivset![253u8..].elements().collect::<Vec<_>>(), vec![253, 254, 255]);
+    }
+
+    #[test]
+    fn nth_element() {
+        let set = ivset!['a'..'c', 'x'..='z'];
+        assert_eq!(set.nth(0), Some('a'));
+        assert_eq!(set.nth(2), Some('x'));
+        assert_eq!(set.nth(4), Some('z'));
+        assert_eq!(set.nth(5), None);
+    }
 }
@@ -0,0 +1,583 @@
+/**
+ * Stores possibly overlapping intervals with associated values, and answers
+ * which entries contain a point or overlap an interval.
+ *
+ * It's a treap ordered by the lower bounds, where every node also stores the
+ * largest upper bound of its subtree, so queries can skip every subtree that
+ * can't have a matching entry.
+ */
+
+use crate::bound::UpperBound;
+use crate::discrete::Discrete;
+use crate::interval::Interval;
+use crate::shift::{Shift, Splice, SplicePolicy};
+
+type Link<K, V> = Option<Box<Node<K, V>>>;
+
+#[derive(Debug, Clone)]
+struct Node<K, V> {
+    interval: Interval<K>,
+    value: V,
+    priority: u64,
+    // The largest upper bound in the subtree
+    max_upper: UpperBound<K>,
+    left: Link<K, V>,
+    right: Link<K, V>,
+}
+
+#[derive(Debug, Clone)]
+pub struct IntervalTree<K, V> {
+    root: Link<K, V>,
+    len: usize,
+    // State of the priority generator
+    seed: u64,
+}
+
+impl <K, V> Default for IntervalTree<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl <K, V> IntervalTree<K, V> {
+    pub fn new() -> Self {
+        Self{ root: None, len: 0, seed: 0x2545_F491_4F6C_DD1D }
+    }
+
+    /// The number of entries in the tree.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.root = None;
+        self.len = 0;
+    }
+
+    fn next_priority(&mut self) -> u64 {
+        // Xorshift, we only need the priorities to look random
+        let mut x = self.seed;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.seed = x;
+        x
+    }
+}
+
+/**
+ * Modification.
+ */
+
+impl <K, V> IntervalTree<K, V> where K : Ord + Clone {
+    /// Inserts an entry. Entries with the same or overlapping intervals are
+    /// all kept.
+    pub fn insert(&mut self, key: Interval<K>, value: V) {
+        let priority = self.next_priority();
+        self.insert_with_priority(key, value, priority);
+        self.len += 1;
+    }
+
+    fn insert_with_priority(&mut self, key: Interval<K>, value: V, priority: u64) {
+        // Equal lower bounds go to the left, so insertion order is kept for those
+        let (left, right) = split(self.root.take(), &|iv: &Interval<K>| iv.lower <= key.lower);
+        let node = Box::new(Node{
+            max_upper: key.upper.clone(),
+            interval: key,
+            value,
+            priority,
+            left: None,
+            right: None,
+        });
+        self.root = merge(merge(left, Some(node)), right);
+    }
+
+    /// Removes an entry with exactly the given interval, returning its value.
+    pub fn remove(&mut self, key: &Interval<K>) -> Option<V> {
+        let (root, removed) = remove_first(self.root.take(), key);
+        self.root = root;
+        if removed.is_some() {
+            self.len -= 1;
+        }
+        removed
+    }
+
+    /// Removes every entry that overlaps the given interval, returning them
+    /// in order.
+    pub fn remove_overlapping(&mut self, interval: &Interval<K>) -> Vec<(Interval<K>, V)> {
+        let mut removed = Vec::new();
+        self.root = remove_overlapping(self.root.take(), interval, &mut removed);
+        self.len -= removed.len();
+        removed
+    }
+}
+
+impl <K, V> IntervalTree<K, V> where K : Ord + Clone + Shift {
+    /// Moves every entry that starts at or after the given point by the
+    /// offset. Entries starting before the point are left untouched, even if
+    /// they extend beyond it.
+    pub fn shift_after(&mut self, point: &K, offset: &K::Offset) {
+        let (before, mut after) = split(self.root.take(), &|iv: &Interval<K>| !iv.starts_at_or_after(point));
+        shift_subtree(&mut after, offset);
+
+        let first_lower = match leftmost(&after) {
+            Some(node) => node.interval.lower.clone(),
+            None => {
+                self.root = before;
+                return;
+            },
+        };
+
+        // Moving backwards can carry entries in front of the ones that stayed
+        // in place, those need to be inserted one by one
+        let (before, between) = split(before, &|iv: &Interval<K>| iv.lower <= first_lower);
+        self.root = merge(before, after);
+        let mut displaced = Vec::new();
+        into_entries(between, &mut displaced);
+        for (key, value, priority) in displaced {
+            self.insert_with_priority(key, value, priority);
+        }
+    }
+}
+
+impl <K, V> IntervalTree<K, V> where K : Ord + Clone + Shift, V : Clone {
+    /// Replaces the erased region with inserted content of the given length,
+    /// moving everything after it. The erased region consists of the elements
+    /// of the interval, so `a..=b` erases the same as `a..b+1`. Entries
+    /// intersecting it are handled according to the policy.
+    pub fn splice(&mut self, erased: &Interval<K>, inserted: &K::Offset, policy: SplicePolicy)
+        where K : Discrete {
+        let splice = Splice::new(erased, inserted, policy);
+        let (rest, mut after) = split(self.root.take(), &|iv: &Interval<K>| !splice.is_after(iv));
+        shift_subtree(&mut after, splice.offset());
+
+        // What remained either ends before the erased region or intersects it
+        let mut affected = Vec::new();
+        let reaching = Interval::with_bounds(erased.lower.clone(), UpperBound::Unbounded);
+        let rest = remove_overlapping(rest, &reaching, &mut affected);
+        self.root = merge(rest, after);
+        self.len -= affected.len();
+
+        for (iv, value) in affected {
+            match splice.pieces(&iv) {
+                (Some(left), Some(right)) => {
+                    self.insert(left, value.clone());
+                    self.insert(right, value);
+                },
+                (Some(piece), None) | (None, Some(piece)) => self.insert(piece, value),
+                (None, None) => {},
+            }
+        }
+    }
+}
+
+/**
+ * Queries.
+ */
+
+impl <K, V> IntervalTree<K, V> where K : Ord {
+    /// Iterates over all entries, ordered by their lower bounds.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter::with_query(&self.root, Interval::full())
+    }
+}
+
+impl <K, V> IntervalTree<K, V> where K : Ord + Clone {
+    /// Iterates over the entries containing the given point.
+    pub fn stabbing(&self, point: &K) -> Iter<'_, K, V> {
+        Iter::with_query(&self.root, Interval::singleton(point.clone()))
+    }
+
+    /// Iterates over the entries having a common element with the given
+    /// interval.
+    pub fn overlapping(&self, interval: &Interval<K>) -> Iter<'_, K, V> {
+        Iter::with_query(&self.root, interval.clone())
+    }
+}
+
+/**
+ * Iteration.
+ */
+
+pub struct Iter<'a, K, V> {
+    query: Interval<K>,
+    // Nodes, whose left subtree is already visited
+    stack: Vec<&'a Node<K, V>>,
+}
+
+impl <'a, K, V> Iter<'a, K, V> where K : Ord {
+    fn with_query(root: &'a Link<K, V>, query: Interval<K>) -> Self {
+        let mut it = Self{ query, stack: Vec::new() };
+        it.descend(root.as_deref());
+        it
+    }
+
+    fn descend(&mut self, mut link: Option<&'a Node<K, V>>) {
+        while let Some(node) = link {
+            if node.max_upper < self.query.lower {
+                // Nothing in this subtree reaches the query
+                break;
+            }
+            self.stack.push(node);
+            link = node.left.as_deref();
+        }
+    }
+}
+
+impl <'a, K, V> Iterator for Iter<'a, K, V> where K : Ord {
+    type Item = (&'a Interval<K>, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(node) = self.stack.pop() {
+            if self.query.upper < node.interval.lower {
+                // This and everything after starts after the query
+                self.stack.clear();
+                return None;
+            }
+            self.descend(node.right.as_deref());
+            if !node.interval.is_disjunct(&self.query) {
+                return Some((&node.interval, &node.value));
+            }
+        }
+        None
+    }
+}
+
+impl <'a, K, V> IntoIterator for &'a IntervalTree<K, V> where K : Ord {
+    type Item = (&'a Interval<K>, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/**
+ * Treap primitives.
+ */
+
+impl <K, V> Node<K, V> where K : Ord + Clone {
+    // Recomputes the largest upper bound from the children
+    fn update(&mut self) {
+        let mut max_upper = self.interval.upper.clone();
+        if let Some(left) = &self.left {
+            if left.max_upper > max_upper {
+                max_upper = left.max_upper.clone();
+            }
+        }
+        if let Some(right) = &self.right {
+            if right.max_upper > max_upper {
+                max_upper = right.max_upper.clone();
+            }
+        }
+        self.max_upper = max_upper;
+    }
+}
+
+// Splits the tree into the entries satisfying the predicate and the rest. The
+// predicate must be true for a prefix of the entries.
+fn split<K, V, F>(link: Link<K, V>, goes_left: &F) -> (Link<K, V>, Link<K, V>)
+    where F : Fn(&Interval<K>) -> bool, K : Ord + Clone {
+
+    match link {
+        None => (None, None),
+
+        Some(mut node) => {
+            if goes_left(&node.interval) {
+                let (left, right) = split(node.right.take(), goes_left);
+                node.right = left;
+                node.update();
+                (Some(node), right)
+            }
+            else {
+                let (left, right) = split(node.left.take(), goes_left);
+                node.left = right;
+                node.update();
+                (left, Some(node))
+            }
+        },
+    }
+}
+
+// Joins two trees, where every entry of the first one must come before the
+// entries of the second one.
+fn merge<K, V>(left: Link<K, V>, right: Link<K, V>) -> Link<K, V> where K : Ord + Clone {
+    match (left, right) {
+        (None, right) => right,
+        (left, None) => left,
+
+        (Some(mut left), Some(mut right)) => {
+            if left.priority > right.priority {
+                left.right = merge(left.right.take(), Some(right));
+                left.update();
+                Some(left)
+            }
+            else {
+                right.left = merge(Some(left), right.left.take());
+                right.update();
+                Some(right)
+            }
+        },
+    }
+}
+
+fn remove_first<K, V>(link: Link<K, V>, key: &Interval<K>) -> (Link<K, V>, Option<V>)
+    where K : Ord + Clone {
+
+    match link {
+        None => (None, None),
+
+        Some(mut node) => {
+            if key.lower < node.interval.lower {
+                let (left, removed) = remove_first(node.left.take(), key);
+                node.left = left;
+                node.update();
+                (Some(node), removed)
+            }
+            else if key.lower > node.interval.lower {
+                let (right, removed) = remove_first(node.right.take(), key);
+                node.right = right;
+                node.update();
+                (Some(node), removed)
+            }
+            else {
+                // Equal lower bounds can be on both sides
+                let (left, removed) = remove_first(node.left.take(), key);
+                node.left = left;
+                if removed.is_some() {
+                    node.update();
+                    return (Some(node), removed);
+                }
+                if node.interval == *key {
+                    let node = *node;
+                    return (merge(node.left, node.right), Some(node.value));
+                }
+                let (right, removed) = remove_first(node.right.take(), key);
+                node.right = right;
+                node.update();
+                (Some(node), removed)
+            }
+        },
+    }
+}
+
+fn remove_overlapping<K, V>(link: Link<K, V>, interval: &Interval<K>,
+    removed: &mut Vec<(Interval<K>, V)>) -> Link<K, V> where K : Ord + Clone {
+
+    let mut node = link?;
+    if node.max_upper < interval.lower {
+        // Nothing in this subtree reaches the interval
+        return Some(node);
+    }
+
+    node.left = remove_overlapping(node.left.take(), interval, removed);
+    let hit = !node.interval.is_disjunct(interval);
+    // Collect the right side separately to keep the order
+    let mut removed_right = Vec::new();
+    if interval.upper >= node.interval.lower {
+        node.right = remove_overlapping(node.right.take(), interval, &mut removed_right);
+    }
+
+    let result = if hit {
+        let node = *node;
+        removed.push((node.interval, node.value));
+        merge(node.left, node.right)
+    }
+    else {
+        node.update();
+        Some(node)
+    };
+    removed.extend(removed_right);
+    result
+}
+
+fn shift_subtree<K, V>(link: &mut Link<K, V>, offset: &K::Offset) where K : Shift {
+    if let Some(node) = link {
+        node.interval = node.interval.shifted(offset);
+        node.max_upper = node.max_upper.shifted(offset);
+        shift_subtree(&mut node.left, offset);
+        shift_subtree(&mut node.right, offset);
+    }
+}
+
+fn leftmost<K, V>(link: &Link<K, V>) -> Option<&Node<K, V>> {
+    let mut node = link.as_deref()?;
+    while let Some(left) = node.left.as_deref() {
+        node = left;
+    }
+    Some(node)
+}
+
+fn into_entries<K, V>(link: Link<K, V>, entries: &mut Vec<(Interval<K>, V, u64)>) {
+    if let Some(node) = link {
+        let node = *node;
+        into_entries(node.left, entries);
+        entries.push((node.interval, node.value, node.priority));
+        into_entries(node.right, entries);
+    }
+}
+
+// Tests ///////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod interval_tree_tests {
+    use super::*;
+
+    fn bound_clone<T>(b: std::ops::Bound<&T>) -> std::ops::Bound<T> where T : Clone {
+        match b {
+            std::ops::Bound::Excluded(x) => std::ops::Bound::Excluded(x.clone()),
+            std::ops::Bound::Included(x) => std::ops::Bound::Included(x.clone()),
+            std::ops::Bound::Unbounded => std::ops::Bound::Unbounded,
+        }
+    }
+
+    fn range_to_interval<R, T>(r: R) -> Interval<T> where R : std::ops::RangeBounds<T>, T : Clone {
+        Interval::with_bounds(bound_clone(r.start_bound()).into(), bound_clone(r.end_bound()).into())
+    }
+
+    // Just to make it easier to type
+    fn ri<R, T>(r: R) -> Interval<T> where R : std::ops::RangeBounds<T>, T : Clone {
+        range_to_interval(r)
+    }
+
+    macro_rules! ivtree {
+        ( $( $x:expr => $y:expr ),* ) => {
+            {
+                let mut iv = IntervalTree::new();
+                $(
+                    iv.insert(ri($x), $y);
+                )*
+                iv
+            }
+        };
+    }
+
+    fn values<'a, I>(it: I) -> Vec<i32> where I : Iterator<Item = (&'a Interval<usize>, &'a i32)> {
+        it.map(|(_, v)| *v).collect()
+    }
+
+    // A tiny deterministic generator for the randomized tests
+    fn lcg(state: &mut u64) -> usize {
+        *state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (*state >> 33) as usize
+    }
+
+    /**
+     * Query tests.
+     */
+
+    #[test]
+    fn iterate_in_order() {
+        let tree = ivtree![5..9 => 1, 1..3 => 0, 7..8 => 2, 5..6 => 3];
+        assert_eq!(values(tree.iter()), vec![0, 1, 3, 2]);
+        assert_eq!(tree.len(), 4);
+    }
+
+    #[test]
+    fn stab_overlapping_entries() {
+        let tree = ivtree![1..10 => 0, 2..4 => 1, 3..5 => 2, 5..7 => 3, 8..=8 => 4];
+        assert_eq!(values(tree.stabbing(&3)), vec![0, 1, 2]);
+        assert_eq!(values(tree.stabbing(&5)), vec![0, 3]);
+        assert_eq!(values(tree.stabbing(&8)), vec![0, 4]);
+        assert_eq!(values(tree.stabbing(&10)), vec![]);
+    }
+
+    #[test]
+    fn range_query() {
+        let tree = ivtree![1..3 => 0, 2..6 => 1, 6..9 => 2, 10.. => 3];
+        assert_eq!(values(tree.overlapping(&ri(3..6))), vec![1]);
+        assert_eq!(values(tree.overlapping(&ri(3..=6))), vec![1, 2]);
+        assert_eq!(values(tree.overlapping(&ri(9..10))), vec![]);
+        assert_eq!(values(tree.overlapping(&ri(..))), vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn queries_match_brute_force() {
+        let mut state = 42;
+        let mut tree = IntervalTree::new();
+        let mut entries = Vec::new();
+        for i in 0..300 {
+            let start = lcg(&mut state) % 1000;
+            let len = lcg(&mut state) % 50;
+            tree.insert(ri(start..(start + len)), i);
+            entries.push((ri(start..(start + len)), i));
+        }
+        for _ in 0..100 {
+            let start = lcg(&mut state) % 1000;
+            let query = ri(start..(start + lcg(&mut state) % 30));
+            let mut expected: Vec<_> = entries.iter()
+                .filter(|(iv, _)| !iv.is_disjunct(&query)).map(|(_, v)| *v).collect();
+            let mut found = values(tree.overlapping(&query));
+            expected.sort();
+            found.sort();
+            assert_eq!(found, expected);
+        }
+    }
+
+    /**
+     * Removal tests.
+     */
+
+    #[test]
+    fn remove_exact() {
+        let mut tree = ivtree![1..3 => 0, 1..5 => 1, 1..3 => 2];
+        assert_eq!(tree.remove(&ri(1..3)), Some(0));
+        assert_eq!(tree.remove(&ri(1..4)), None);
+        assert_eq!(values(tree.iter()), vec![1, 2]);
+        assert_eq!(tree.len(), 2);
+    }
+
+    #[test]
+    fn remove_overlapping_entries() {
+        let mut tree = ivtree![1..3 => 0, 2..6 => 1, 6..9 => 2, 4..5 => 3];
+        let removed: Vec<_> = tree.remove_overlapping(&ri(4..7)).into_iter().map(|(_, v)| v).collect();
+        assert_eq!(removed, vec![1, 3, 2]);
+        assert_eq!(values(tree.iter()), vec![0]);
+        assert_eq!(tree.len(), 1);
+    }
+
+    /**
+     * Shifting tests.
+     */
+
+    #[test]
+    fn shift_forward() {
+        let mut tree = ivtree![1..3 => 0, 2..6 => 1, 6..9 => 2];
+        tree.shift_after(&4, &10);
+        let entries: Vec<_> = tree.iter().map(|(iv, v)| (*iv, *v)).collect();
+        assert_eq!(entries, vec![(ri(1..3), 0), (ri(2..6), 1), (ri(16..19), 2)]);
+        assert_eq!(values(tree.stabbing(&17)), vec![2]);
+    }
+
+    #[test]
+    fn shift_backward_past_unmoved() {
+        let mut tree = ivtree![1..3 => 0, 4..5 => 1, 10..12 => 2, 14..20 => 3];
+        tree.shift_after(&10, &-8);
+        let entries: Vec<_> = tree.iter().map(|(iv, v)| (*iv, *v)).collect();
+        assert_eq!(entries, vec![(ri(1..3), 0), (ri(2..4), 2), (ri(4..5), 1), (ri(6..12), 3)]);
+        assert_eq!(values(tree.stabbing(&11)), vec![3]);
+    }
+
+    #[test]
+    fn splice_drop() {
+        let mut tree = ivtree![1..3 => 0, 2..6 => 1, 5..8 => 2, 8..9 => 3, 0..20 => 4];
+        tree.splice(&ri(4..7), &1, SplicePolicy::Drop);
+        let entries: Vec<_> = tree.iter().map(|(iv, v)| (*iv, *v)).collect();
+        assert_eq!(entries, vec![(ri(1..3), 0), (ri(6..7), 3)]);
+        assert_eq!(tree.len(), 2);
+    }
+
+    #[test]
+    fn splice_clip() {
+        let mut tree = ivtree![1..3 => 0, 2..6 => 1, 5..8 => 2, 8..9 => 3];
+        tree.splice(&ri(4..7), &1, SplicePolicy::Clip);
+        let entries: Vec<_> = tree.iter().map(|(iv, v)| (*iv, *v)).collect();
+        assert_eq!(entries, vec![(ri(1..3), 0), (ri(2..4), 1), (ri(5..6), 2), (ri(6..7), 3)]);
+        assert_eq!(tree.len(), 4);
+        assert_eq!(values(tree.stabbing(&5)), vec![2]);
+    }
+}
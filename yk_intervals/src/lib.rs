@@ -1,10 +1,18 @@
 
 mod bound;
+mod discrete;
 mod interval;
 mod interval_map;
 mod interval_set;
+mod interval_tree;
+mod notation;
+mod shift;
 
 pub use bound::{LowerBound, UpperBound};
+pub use discrete::Discrete;
 pub use interval::{Interval, IntervalRelation};
 pub use interval_map::IntervalMap;
-pub use interval_set::IntervalSet;
+pub use interval_set::{IntervalSet, Elements};
+pub use interval_tree::IntervalTree;
+pub use notation::ParseIntervalError;
+pub use shift::{Shift, SplicePolicy};
@@ -0,0 +1,294 @@
+/**
+ * Textual notation for the interval types, using the mathematical form:
+ *  - Intervals: [a; z), (-inf; 5]
+ *  - Sets: {[a; c], [x; z]}
+ *  - Maps: {[a; c] => 1, [x; z] => 2}
+ *
+ * Elements and values are written with their Display implementation and
+ * parsed back with FromStr.
+ */
+
+use std::fmt;
+use std::str::FromStr;
+use crate::bound::{LowerBound, UpperBound};
+use crate::interval::Interval;
+use crate::interval_map::IntervalMap;
+use crate::interval_set::IntervalSet;
+
+/// The error for a text that is not in interval notation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseIntervalError {
+    message: &'static str,
+}
+
+impl ParseIntervalError {
+    fn new(message: &'static str) -> Self {
+        Self{ message }
+    }
+}
+
+impl fmt::Display for ParseIntervalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for ParseIntervalError {}
+
+/**
+ * Printing.
+ */
+
+impl <T> fmt::Display for Interval<T> where T : fmt::Display {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.lower {
+            LowerBound::Excluded(x) => write!(f, "({}", x)?,
+            LowerBound::Included(x) => write!(f, "[{}", x)?,
+            LowerBound::Unbounded => write!(f, "(-inf")?,
+        }
+        write!(f, "; ")?;
+        match &self.upper {
+            UpperBound::Excluded(x) => write!(f, "{})", x),
+            UpperBound::Included(x) => write!(f, "{}]", x),
+            UpperBound::Unbounded => write!(f, "+inf)"),
+        }
+    }
+}
+
+impl <T> fmt::Display for IntervalSet<T> where T : fmt::Display {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        for (i, iv) in self.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", iv)?;
+        }
+        write!(f, "}}")
+    }
+}
+
+impl <K, V> fmt::Display for IntervalMap<K, V> where K : fmt::Display, V : fmt::Display {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        for (i, (iv, v)) in self.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{} => {}", iv, v)?;
+        }
+        write!(f, "}}")
+    }
+}
+
+/**
+ * Parsing.
+ *
+ * Since elements can contain the separators themselves (like the character
+ * ';'), every possible split is tried and the shortest one that parses wins.
+ */
+
+// Parses the shortest prefix that is followed by one of the terminators or the
+// end of the text
+fn parse_prefix<'a, T>(s: &'a str, terminators: &[&str]) -> Option<(T, &'a str)> where T : FromStr {
+    for (i, _) in s.char_indices().skip(1).chain(std::iter::once((s.len(), ' '))) {
+        let rest = &s[i..];
+        if !rest.is_empty() && !terminators.iter().any(|t| rest.starts_with(t)) {
+            continue;
+        }
+        if let Ok(value) = s[..i].parse() {
+            return Some((value, rest));
+        }
+    }
+    None
+}
+
+// Parses the comma-separated entries between braces
+fn parse_entries<T, F>(s: &str, mut parse_entry: F) -> Result<Vec<T>, ParseIntervalError>
+    where F : FnMut(&str) -> Option<(T, &str)> {
+
+    let s = s.trim();
+    let mut rest = s.strip_prefix('{')
+        .and_then(|s| s.strip_suffix('}'))
+        .ok_or_else(|| ParseIntervalError::new("Expected entries between braces!"))?;
+
+    let mut result = Vec::new();
+    while !rest.is_empty() {
+        if !result.is_empty() {
+            rest = rest.strip_prefix(", ").ok_or_else(|| ParseIntervalError::new("Expected ', ' between entries!"))?;
+        }
+        let (entry, next) = parse_entry(rest).ok_or_else(|| ParseIntervalError::new("Invalid entry!"))?;
+        result.push(entry);
+        rest = next;
+    }
+    Ok(result)
+}
+
+impl <T> FromStr for Interval<T> where T : FromStr {
+    type Err = ParseIntervalError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let mut chars = s.chars();
+        let open = chars.next();
+        let close = chars.next_back();
+        let body = chars.as_str();
+
+        let (lower_str, upper_str) = body.match_indices("; ")
+            .map(|(i, sep)| (&body[..i], &body[(i + sep.len())..]))
+            .find(|(l, u)| parse_lower::<T>(open, l).is_ok() && parse_upper::<T>(close, u).is_ok())
+            .ok_or_else(|| ParseIntervalError::new("Expected an interval in the form [lower; upper)!"))?;
+
+        Ok(Interval::with_bounds(parse_lower(open, lower_str)?, parse_upper(close, upper_str)?))
+    }
+}
+
+fn parse_lower<T>(open: Option<char>, s: &str) -> Result<LowerBound<T>, ParseIntervalError> where T : FromStr {
+    let value = || s.parse().map_err(|_| ParseIntervalError::new("Invalid lower bound!"));
+    match open {
+        Some('(') if s == "-inf" => Ok(LowerBound::Unbounded),
+        Some('(') => Ok(LowerBound::Excluded(value()?)),
+        Some('[') => Ok(LowerBound::Included(value()?)),
+        _ => Err(ParseIntervalError::new("Expected '(' or '[' at the start of an interval!")),
+    }
+}
+
+fn parse_upper<T>(close: Option<char>, s: &str) -> Result<UpperBound<T>, ParseIntervalError> where T : FromStr {
+    let value = || s.parse().map_err(|_| ParseIntervalError::new("Invalid upper bound!"));
+    match close {
+        Some(')') if s == "+inf" => Ok(UpperBound::Unbounded),
+        Some(')') => Ok(UpperBound::Excluded(value()?)),
+        Some(']') => Ok(UpperBound::Included(value()?)),
+        _ => Err(ParseIntervalError::new("Expected ')' or ']' at the end of an interval!")),
+    }
+}
+
+impl <T> FromStr for IntervalSet<T> where T : FromStr + Ord {
+    type Err = ParseIntervalError;
+
+    /// Parses the set, unifying the intervals as if they were inserted one by
+    /// one.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let intervals = parse_entries(s, |s| parse_prefix(s, &[", "]))?;
+        Ok(intervals.into_iter().collect())
+    }
+}
+
+impl <K, V> FromStr for IntervalMap<K, V> where K : FromStr + Ord, V : FromStr {
+    type Err = ParseIntervalError;
+
+    /// Parses the map as written, the keys must be in order and disjunct.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let intervals = parse_entries(s, |s| {
+            let (iv, rest) = parse_prefix::<Interval<K>>(s, &[" => "])?;
+            let (value, rest) = parse_prefix(rest.strip_prefix(" => ")?, &[", (", ", ["])?;
+            Some(((iv, value), rest))
+        })?;
+        let ordered = intervals.windows(2).all(|w: &[(Interval<K>, V)]| w[0].0.is_before(&w[1].0));
+        if !ordered {
+            return Err(ParseIntervalError::new("The keys must be in order and disjunct!"));
+        }
+        Ok(IntervalMap{ intervals })
+    }
+}
+
+// Tests ///////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod notation_tests {
+    use super::*;
+
+    fn bound_clone<T>(b: std::ops::Bound<&T>) -> std::ops::Bound<T> where T : Clone {
+        match b {
+            std::ops::Bound::Excluded(x) => std::ops::Bound::Excluded(x.clone()),
+            std::ops::Bound::Included(x) => std::ops::Bound::Included(x.clone()),
+            std::ops::Bound::Unbounded => std::ops::Bound::Unbounded,
+        }
+    }
+
+    fn range_to_interval<R, T>(r: R) -> Interval<T> where R : std::ops::RangeBounds<T>, T : Clone {
+        Interval::with_bounds(bound_clone(r.start_bound()).into(), bound_clone(r.end_bound()).into())
+    }
+
+    // Just to make it easier to type
+    fn ri<R, T>(r: R) -> Interval<T> where R : std::ops::RangeBounds<T>, T : Clone {
+        range_to_interval(r)
+    }
+
+    /**
+     * Printing tests.
+     */
+
+    #[test]
+    fn print_intervals() {
+        assert_eq!(ri('a'..'z').to_string(), "[a; z)");
+        assert_eq!(ri(..=5).to_string(), "(-inf; 5]");
+        assert_eq!(ri(3..).to_string(), "[3; +inf)");
+        assert_eq!(Interval::with_bounds(LowerBound::Excluded(1), UpperBound::Included(2)).to_string(), "(1; 2]");
+    }
+
+    #[test]
+    fn print_set() {
+        let set: IntervalSet<char> = vec![ri('a'..='c'), ri('x'..='z')].into_iter().collect();
+        assert_eq!(set.to_string(), "{[a; c], [x; z]}");
+        assert_eq!(IntervalSet::<char>::new().to_string(), "{}");
+    }
+
+    #[test]
+    fn print_map() {
+        let map = IntervalMap{ intervals: vec![(ri('a'..='c'), 1), (ri('x'..), 2)] };
+        assert_eq!(map.to_string(), "{[a; c] => 1, [x; +inf) => 2}");
+    }
+
+    /**
+     * Parsing tests.
+     */
+
+    #[test]
+    fn parse_intervals() {
+        assert_eq!("[a; z)".parse(), Ok(ri('a'..'z')));
+        assert_eq!("(-inf; 5]".parse(), Ok(ri(..=5)));
+        assert_eq!("[-3; +inf)".parse(), Ok(ri(-3..)));
+        assert_eq!("(-inf; +inf)".parse(), Ok(ri::<_, i32>(..)));
+    }
+
+    #[test]
+    fn parse_separator_characters() {
+        assert_eq!("[;; ;]".parse(), Ok(ri(';'..=';')));
+        assert_eq!("[ ; )]".parse(), Ok(ri(' '..=')')));
+    }
+
+    #[test]
+    fn parse_invalid_intervals() {
+        assert!("[a, z)".parse::<Interval<char>>().is_err());
+        assert!("a; z".parse::<Interval<char>>().is_err());
+        assert!("[-inf; 5]".parse::<Interval<i32>>().is_err());
+        assert!("[1; x]".parse::<Interval<i32>>().is_err());
+    }
+
+    #[test]
+    fn parse_set() {
+        let set: IntervalSet<char> = "{[a; c], [,; ,], [b; f)}".parse().unwrap();
+        assert_eq!(set.to_string(), "{[,; ,], [a; f)}");
+        assert_eq!("{}".parse(), Ok(IntervalSet::<char>::new()));
+    }
+
+    #[test]
+    fn parse_map() {
+        let map: IntervalMap<char, usize> = "{[a; c] => 1, [x; +inf) => 2}".parse().unwrap();
+        assert_eq!(map, IntervalMap{ intervals: vec![(ri('a'..='c'), 1), (ri('x'..), 2)] });
+    }
+
+    #[test]
+    fn parse_unordered_map() {
+        assert!("{[x; z] => 1, [a; c] => 2}".parse::<IntervalMap<char, usize>>().is_err());
+    }
+
+    #[test]
+    fn round_trip() {
+        let text = "{(-inf; 3), [5; 5], (7; +inf) => 4}";
+        assert!(text.parse::<IntervalMap<i32, i32>>().is_err());
+        let text = "{(-inf; 3) => 1, [5; 5] => 2, (7; +inf) => 4}";
+        assert_eq!(text.parse::<IntervalMap<i32, i32>>().unwrap().to_string(), text);
+    }
+}
@@ -0,0 +1,187 @@
+/**
+ * Domains that can be moved by some offset, like positions in a source text,
+ * and the description of text edits over them.
+ */
+
+use std::convert::TryFrom;
+use crate::bound::{LowerBound, UpperBound};
+use crate::discrete::Discrete;
+use crate::interval::Interval;
+
+/// Represents values that can be shifted by a (possibly negative) offset
+pub trait Shift : Sized {
+    type Offset : Clone;
+
+    /// Returns the value moved by the given offset. Panics if the result is
+    /// not representable.
+    fn shift(&self, offset: &Self::Offset) -> Self;
+
+    /// Returns the offset that shifts this value to the other one. Panics if
+    /// the offset is not representable.
+    fn offset_to(&self, other: &Self) -> Self::Offset;
+}
+
+/**
+ * Unsigned integers, shifted by their signed counterpart.
+ */
+
+macro_rules! impl_shift_for_unsigned {
+    ( $( $t:ty => $o:ty ),* ) => {
+        $(
+            impl Shift for $t {
+                type Offset = $o;
+
+                fn shift(&self, offset: &Self::Offset) -> Self {
+                    let res = if *offset >= 0 {
+                        self.checked_add(*offset as $t)
+                    }
+                    else {
+                        self.checked_sub(offset.unsigned_abs())
+                    };
+                    res.expect("Shifted out of the representable range!")
+                }
+
+                fn offset_to(&self, other: &Self) -> Self::Offset {
+                    let res = if other >= self {
+                        <$o>::try_from(other - self).ok()
+                    }
+                    else {
+                        <$o>::try_from(self - other).ok().map(|d| -d)
+                    };
+                    res.expect("The offset is not representable!")
+                }
+            }
+        )*
+    };
+}
+
+/**
+ * Signed integers, shifted by the same type.
+ */
+
+macro_rules! impl_shift_for_signed {
+    ( $( $t:ty ),* ) => {
+        $(
+            impl Shift for $t {
+                type Offset = $t;
+
+                fn shift(&self, offset: &Self::Offset) -> Self {
+                    self.checked_add(*offset).expect("Shifted out of the representable range!")
+                }
+
+                fn offset_to(&self, other: &Self) -> Self::Offset {
+                    other.checked_sub(*self).expect("The offset is not representable!")
+                }
+            }
+        )*
+    };
+}
+
+impl_shift_for_unsigned!(u8 => i8, u16 => i16, u32 => i32, u64 => i64, u128 => i128, usize => isize);
+impl_shift_for_signed!(i8, i16, i32, i64, i128, isize);
+
+/**
+ * Splicing, replacing a region with some new content.
+ */
+
+/// Decides what happens to the entries that intersect the erased region of a
+/// splice.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SplicePolicy {
+    /// The entries are removed.
+    Drop,
+    /// The erased parts are cut off, the parts before and after the erased
+    /// region are kept.
+    Clip,
+}
+
+// Precomputed description of a splice for the containers
+pub(crate) struct Splice<T> where T : Shift {
+    // Entries ending at or before this are untouched
+    before: UpperBound<T>,
+    // Entries starting at or after this are moved
+    after: LowerBound<T>,
+    offset: T::Offset,
+    policy: SplicePolicy,
+}
+
+impl <T> Splice<T> where T : Shift + Discrete {
+    // The erased region consists of the elements of the interval, so the
+    // content after it moves from the first element past its upper bound
+    pub(crate) fn new(erased: &Interval<T>, inserted: &T::Offset, policy: SplicePolicy) -> Self {
+        let before = erased.lower.touching().expect("The erased region must be bounded!");
+        let after = erased.upper.touching().expect("The erased region must be bounded!");
+        let start = erased.lower.to_included().expect("The erased region must not start after the domain!");
+        // Without elements after the region there's nothing to move
+        let offset = match after.to_included() {
+            Some(end) => end.offset_to(&start.shift(inserted)),
+            None => start.offset_to(&start),
+        };
+        Self{ before, after, offset, policy, }
+    }
+
+    pub(crate) fn offset(&self) -> &T::Offset {
+        &self.offset
+    }
+
+    pub(crate) fn is_before(&self, iv: &Interval<T>) -> bool {
+        iv.upper <= self.before
+    }
+
+    pub(crate) fn is_after(&self, iv: &Interval<T>) -> bool {
+        iv.lower >= self.after
+    }
+
+    // The remaining parts of the interval before and after the erased region,
+    // already moved to their new place
+    pub(crate) fn pieces(&self, iv: &Interval<T>) -> (Option<Interval<T>>, Option<Interval<T>>) {
+        if self.is_before(iv) {
+            (Some(iv.clone()), None)
+        }
+        else if self.is_after(iv) {
+            (None, Some(iv.shifted(&self.offset)))
+        }
+        else if self.policy == SplicePolicy::Drop {
+            (None, None)
+        }
+        else {
+            let left = Interval::with_bounds(iv.lower.clone(), self.before.clone());
+            let right = Interval::with_bounds(self.after.clone(), iv.upper.clone());
+            let left = if left.is_empty() { None } else { Some(left) };
+            let right = if right.is_empty() { None } else { Some(right.shifted(&self.offset)) };
+            (left, right)
+        }
+    }
+}
+
+// Tests ///////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod shift_tests {
+    use super::*;
+
+    #[test]
+    fn shift_unsigned() {
+        assert_eq!(5usize.shift(&3), 8);
+        assert_eq!(5usize.shift(&-3), 2);
+        assert_eq!(5usize.shift(&-5), 0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn shift_unsigned_below_zero() {
+        5usize.shift(&-6);
+    }
+
+    #[test]
+    fn shift_signed() {
+        assert_eq!(5i32.shift(&-8), -3);
+    }
+
+    #[test]
+    fn offset_between() {
+        assert_eq!(3usize.offset_to(&8), 5);
+        assert_eq!(8usize.offset_to(&3), -5);
+        assert_eq!((-2i32).offset_to(&-7), -5);
+    }
+}
@@ -0,0 +1,137 @@
+/**
+ * Compares the match-based and the table-driven lexer backends on the Lua
+ * token set of 'yk_incremental_test', lexing both the text and its bytes.
+ * Run with 'cargo bench -p yk_lexer'.
+ */
+
+use std::time::{Duration, Instant};
+use yk_lexer::{Lexer, TokenType};
+
+// The same token set for both backends. Whitespace is a regular token, as the
+// 'ignore' helper attribute is ambiguous with the built-in one on newer
+// compilers.
+macro_rules! lua_tokens {
+    ($name:ident $(#[$backend:meta])*) => {
+        #[derive(Lexer, Clone, Copy, PartialEq, Eq, Debug)]
+        $(#[$backend])*
+        pub enum $name {
+            #[error] Error,
+            #[end] EndOfInput,
+
+            #[regex(r"[ \r\n\t]")] Ws,
+
+            #[regex(r"[0-9]+")] IntLit,
+            #[c_ident] Ident,
+
+            #[token("or")] Or,
+            #[token("and")] And,
+            #[token("<")] Lt,
+            #[token("<=")] Le,
+            #[token(">")] Gt,
+            #[token(">=")] Ge,
+            #[token("==")] Eq,
+            #[token("~=")] Neq,
+            #[token("..")] Cat,
+            #[token("+")] Add,
+            #[token("-")] Sub,
+            #[token("*")] Mul,
+            #[token("/")] Div,
+            #[token("%")] Mod,
+            #[token("not")] Not,
+            #[token("#")] Hash,
+            #[token("^")] Exp,
+
+            #[token("=")] Asgn,
+
+            #[token(";")] Sc,
+            #[token(".")] Dot,
+            #[token(",")] Comma,
+
+            #[token("...")] Ellipsis,
+
+            #[token("function")] Function,
+            #[token("do")] Do,
+            #[token("while")] While,
+            #[token("repeat")] Repeat,
+            #[token("until")] Until,
+            #[token("if")] If,
+            #[token("else")] Else,
+            #[token("elseif")] ElseIf,
+            #[token("for")] For,
+            #[token("end")] End,
+            #[token("break")] Break,
+            #[token("return")] Return,
+            #[token("local")] Local,
+
+            #[token("nil")] Nil,
+            #[token("true")] True,
+            #[token("false")] False,
+
+            #[token("(")] LeftParen,
+            #[token(")")] RightParen,
+
+            #[token("[")] LeftBracket,
+            #[token("]")] RightBracket,
+
+            #[token("{")] LeftBrace,
+            #[token("}")] RightBrace,
+        }
+    };
+}
+
+lua_tokens!(MatchTok);
+lua_tokens!(TableTok #[table_driven]);
+
+const ITERATIONS: usize = 10;
+
+// A Lua-like source with every kind of token
+fn source(functions: usize) -> String {
+    let mut source = String::new();
+    for i in 0..functions {
+        source += &format!("local function fn_{}(a, b, ...)\n", i);
+        source += "    local t = { 1, 2, [3] = a .. b, x = #a ^ 2 }\n";
+        source += &format!("    for i = 1, {} do\n", i % 100 + 1);
+        source += "        if a <= b and not (a >= 3) or a ~= b then t.x = t.x + i * 2 / 3 % 4 - 1;\n";
+        source += "        elseif a == nil then break else repeat a = a - 1 until a < 0 end\n";
+        source += "    end\n";
+        source += "    while true do return t, false end\n";
+        source += "end\n";
+    }
+    source
+}
+
+// Lexes the whole source a few times, returning the best time and the token
+// count for the text and for the bytes
+fn measure<T>(source: &str) -> ((Duration, usize), (Duration, usize)) where T : TokenType + PartialEq {
+    let mut lexer = T::lexer();
+    lexer.modify(&[], 0..0, source);
+
+    let text = best_of(|| lexer.iter().count());
+    let bytes = best_of(|| lexer.iter_bytes(source.as_bytes()).count());
+    (text, bytes)
+}
+
+fn best_of<F>(mut lex: F) -> (Duration, usize) where F : FnMut() -> usize {
+    let mut best = Duration::MAX;
+    let mut count = 0;
+    for _ in 0..ITERATIONS {
+        let start = Instant::now();
+        count = lex();
+        best = best.min(start.elapsed());
+    }
+    (best, count)
+}
+
+fn main() {
+    let source = source(5000);
+    println!("Source: {} bytes", source.len());
+
+    for (backend, counts, ((elapsed, tokens), (byte_elapsed, byte_tokens))) in [
+        ("match", MatchTok::state_counts(), measure::<MatchTok>(&source)),
+        ("table", TableTok::state_counts(), measure::<TableTok>(&source)),
+    ] {
+        println!("{} backend: {} tokens in {} ms ({} states, {} before minimization)",
+            backend, tokens, elapsed.as_millis(), counts.minimized, counts.determinized);
+        println!("{} backend on bytes: {} tokens in {} ms", backend, byte_tokens, byte_elapsed.as_millis());
+    }
+}
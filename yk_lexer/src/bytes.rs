@@ -0,0 +1,166 @@
+/**
+ * Lexing UTF-8 bytes without decoding them first. The automata of the lexers
+ * are compiled to read bytes, so invalid UTF-8 is never matched and becomes
+ * error tokens.
+ */
+
+use crate::lexer::{LexerState, TokenMatcher, next_token};
+use crate::table::{Accepting, Split, NO_STATE};
+use crate::token::Token;
+
+/**
+ * Matching on bytes, shared by the lexers.
+ */
+
+// Matches the next token with a byte automaton, given by its start state, its
+// transition function and its accepting function. The latter returns the
+// token kind, None for ignored tokens, and the split of the trailing context
+// for the accepting states.
+pub(crate) fn next_byte_lexeme<T, S, F, A>(src: &[u8], lex_state: &LexerState, error: &T, end: &T,
+    start: S, mut step: F, mut accepting: A) -> (LexerState, Option<T>, usize)
+    where T : Clone, S : Copy, F : FnMut(S, u8) -> Option<S>, A : FnMut(S) -> Option<(Option<T>, Option<Split>)> {
+
+    let start_idx = lex_state.source_index;
+    let mut current_state = start;
+
+    let mut last_accepting = None; // Option<(end, Option<token>)>
+    // The end of a later match with a trailing context, which is only split
+    // once the match is final
+    let mut last_trailing_context = None; // Option<(end, Option<token>, split)>
+    let mut lookahead = src.len();
+    let mut char_start = start_idx; // Where the current character begins
+
+    for (idx, byte) in src.iter().enumerate().skip(start_idx) {
+        if byte & 0xC0 != 0x80 {
+            char_start = idx;
+        }
+        match step(current_state, *byte) {
+            Some(next) => current_state = next,
+            None => {
+                // The whole character is looked at, like when lexing chars
+                lookahead = (char_start + first_char_len(&src[char_start..])).max(idx + 1);
+                break;
+            },
+        }
+        match accepting(current_state) {
+            Some((kind, None)) => {
+                last_accepting = Some((idx + 1, kind));
+                last_trailing_context = None;
+            },
+            Some((kind, Some(split))) => last_trailing_context = Some((idx + 1, kind, split)),
+            None => {},
+        }
+    }
+
+    // The matched bytes are valid UTF-8, so the split can work on the text.
+    // It has a split, unless only an empty token could be split off, then the
+    // match before it counts.
+    if let Some((end, kind, split)) = last_trailing_context {
+        let text = std::str::from_utf8(&src[start_idx..end]).unwrap();
+        let text_start = LexerState{ source_index: 0, ..lex_state.clone() };
+        let text_end = LexerState{ source_index: text.len(), ..lex_state.clone() };
+        if let Some(mut state) = split(text, &text_start, &text_end) {
+            state.source_index += start_idx;
+            return (state, kind, lookahead.max(end));
+        }
+    }
+
+    let (token_end, kind) = match last_accepting {
+        // We succeeded before, return that
+        Some((token_end, kind)) => (token_end, kind),
+        // No success before, the error is a character or an invalid sequence
+        None if start_idx < src.len() => (start_idx + first_char_len(&src[start_idx..]), Some(error.clone())),
+        // Nothing consumed, no more characters, it's just the end on input
+        None => return (lex_state.clone(), Some(end.clone()), lookahead),
+    };
+    // Only the matched bytes are decoded, for the position
+    let mut state = lex_state.clone();
+    state.advance_bytes(&src[start_idx..token_end]);
+    (state, kind, lookahead.max(token_end))
+}
+
+// The length of the first character of the bytes, or the invalid UTF-8
+// sequence it starts with
+fn first_char_len(bytes: &[u8]) -> usize {
+    if bytes.first().is_some_and(u8::is_ascii) {
+        return 1;
+    }
+    // No character is longer than 4 bytes
+    let prefix = &bytes[..bytes.len().min(4)];
+    match std::str::from_utf8(prefix) {
+        Ok(valid) => valid.chars().next().map_or(0, char::len_utf8),
+        Err(err) if err.valid_up_to() > 0 => {
+            let valid = std::str::from_utf8(&prefix[..err.valid_up_to()]).unwrap();
+            valid.chars().next().map_or(0, char::len_utf8)
+        },
+        Err(err) => err.error_len().unwrap_or(prefix.len()),
+    }
+}
+
+/**
+ * The byte tables of the lexers generated by the derive-macro.
+ */
+
+/// The compressed tables of a lexer automaton reading UTF-8 bytes. The bytes
+/// are grouped into classes and the rows are shared like in `LexerTable`.
+pub struct ByteTable<T : 'static> {
+    /// The class of each byte
+    pub classes: [u32; 256],
+    /// The number of classes, which is the length of a row
+    pub class_count: usize,
+    /// The offset of the row for each state in the transition table
+    pub row_offsets: &'static [u32],
+    /// The rows after each other, the next state for each class
+    pub transitions: &'static [u32],
+    /// The action for each state
+    pub accepting: &'static [Accepting<T>],
+    pub start: u32,
+    pub error: T,
+    pub end: T,
+}
+
+impl <T> ByteTable<T> where T : Clone {
+    /// Matches the next token on the bytes. The invalid UTF-8 sequences are
+    /// never matched, so they become errors.
+    pub fn next_lexeme(&self, src: &[u8], lex_state: &LexerState) -> (LexerState, Option<T>, usize) {
+        next_byte_lexeme(src, lex_state, &self.error, &self.end, self.start,
+            |state, byte| {
+                let offset = self.row_offsets[state as usize] as usize;
+                let next = self.transitions[offset + self.classes[byte as usize] as usize];
+                if next == NO_STATE { None } else { Some(next) }
+            },
+            |state| match &self.accepting[state as usize] {
+                Accepting::No => None,
+                Accepting::Token(kind) => Some((kind.clone(), None)),
+                Accepting::TrailingContext(kind, split) => Some((kind.clone(), Some(*split))),
+            })
+    }
+}
+
+/**
+ * Iterate over the tokens of bytes.
+ */
+
+pub struct ByteIter<'a, T> {
+    source: &'a [u8],
+    state: LexerState,
+    already_ended: bool,
+    matcher: &'a dyn TokenMatcher<TokenTag = T>,
+}
+
+impl <'a, T> ByteIter<'a, T> {
+    pub(crate) fn with_source(source: &'a [u8], matcher: &'a dyn TokenMatcher<TokenTag = T>) -> Self {
+        Self{ source, state: LexerState::new(), already_ended: false, matcher, }
+    }
+}
+
+impl <'a, T> Iterator for ByteIter<'a, T> {
+    type Item = Token<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (source, matcher) = (self.source, self.matcher);
+        next_token(&mut self.state, &mut self.already_ended, matcher,
+            |state| matcher.next_byte_lexeme(source, state),
+            |range| String::from_utf8_lossy(&source[range]).into_owned())
+    }
+}
@@ -0,0 +1,195 @@
+/**
+ * A lexer that is built at runtime from token rules, for languages that are
+ * not known at compile time.
+ */
+
+use std::cell::Cell;
+use std::fmt;
+use std::ops::Range;
+use yk_dense_fsa::{nfa, dfa};
+use yk_dense_fsa::yk_regex_parse as regex;
+use crate::lexer::{LexerState, Lexer, Iter, Modification, TokenMatcher, relex};
+use crate::token::Token;
+use crate::bytes::{ByteIter, next_byte_lexeme};
+
+/**
+ * Errors.
+ */
+
+/// Describes why the rules of a dynamic lexer are invalid
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DynamicLexerError {
+    /// The regex of the rule at the index could not be parsed
+    Regex{
+        rule: usize,
+        error: regex::ParseError,
+    },
+    /// The rules at the indices can match the same text with the same priority
+    Conflict{
+        first: usize,
+        second: usize,
+    },
+}
+
+impl fmt::Display for DynamicLexerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DynamicLexerError::Regex{ rule, error } =>
+                write!(f, "Invalid regex in rule {}: {}", rule, error),
+            DynamicLexerError::Conflict{ first, second } =>
+                write!(f, "Rules {} and {} are conflicting!", first, second),
+        }
+    }
+}
+
+impl std::error::Error for DynamicLexerError {}
+
+/**
+ * Matching with the automaton built from the rules.
+ */
+
+struct DynamicMatcher<T> {
+    // The accepting values are the priority and the index of the rule
+    dfa: dfa::Automaton<char, (usize, usize)>,
+    // The same automaton reading UTF-8 bytes
+    bytes: dfa::Automaton<u8, (usize, usize)>,
+    // The token kind and whether it's ignored, for each rule
+    kinds: Vec<(T, bool)>,
+    error: T,
+    end: T,
+}
+
+impl <T> DynamicMatcher<T> where T : Clone {
+    // The token kind of the rule, None if it's ignored
+    fn kind_of(&self, rule: usize) -> Option<T> {
+        let (kind, ignore) = &self.kinds[rule];
+        if *ignore { None } else { Some(kind.clone()) }
+    }
+}
+
+impl <T> TokenMatcher for DynamicMatcher<T> where T : Clone + PartialEq {
+    type TokenTag = T;
+
+    fn next_lexeme(&self, src: &str, lex_state: &LexerState) -> (LexerState, Option<T>, usize) {
+        let mut current_state = self.dfa.start;
+
+        let mut last_accepting = None; // Option<(state, rule)>
+        let mut first_lex_state = None; // Option<state>
+        let mut last_lex_state = lex_state.clone();
+
+        for current_char in src[lex_state.source_index..].chars() {
+            last_lex_state.advance(current_char);
+
+            // Save if first
+            if first_lex_state.is_none() {
+                first_lex_state = Some(last_lex_state.clone());
+            }
+
+            match self.dfa.step(current_state, &current_char) {
+                Some(next) => current_state = next,
+                None => break,
+            }
+            if let Some((_, rule)) = self.dfa.accepting_value(&current_state) {
+                last_accepting = Some((last_lex_state.clone(), *rule));
+            }
+        }
+
+        let lookahead = last_lex_state.source_index;
+        match (last_accepting, first_lex_state) {
+            // We succeeded before, return that
+            (Some((state, rule)), _) => (state, self.kind_of(rule), lookahead),
+            // No success before, return an error
+            (None, Some(state)) => (state, Some(self.error.clone()), lookahead),
+            // Nothing consumed, no more characters, it's just the end on input
+            (None, None) => (lex_state.clone(), Some(self.end.clone()), lookahead),
+        }
+    }
+
+    fn next_byte_lexeme(&self, src: &[u8], lex_state: &LexerState) -> (LexerState, Option<T>, usize) {
+        next_byte_lexeme(src, lex_state, &self.error, &self.end, self.bytes.start,
+            |state, byte| self.bytes.step(state, &byte),
+            |state| self.bytes.accepting_value(&state).map(|(_, rule)| (self.kind_of(*rule), None)))
+    }
+
+    fn is_end(&self, kind: &T) -> bool {
+        *kind == self.end
+    }
+}
+
+/**
+ * The lexer itself.
+ */
+
+pub struct DynamicLexer<T = usize> {
+    source: String,
+    matcher: DynamicMatcher<T>,
+    longest_lookahead: Cell<usize>,
+}
+
+impl <T> DynamicLexer<T> where T : Clone + PartialEq {
+    /// Builds the lexer from (kind, regex, priority, ignore) rules. If multiple
+    /// rules match the longest text, the one with the highest priority wins.
+    /// The error and end kinds are returned for unmatched characters and at
+    /// the end of the source.
+    pub fn new<S>(rules: Vec<(T, S, usize, bool)>, error: T, end: T) -> Result<Self, DynamicLexerError>
+        where S : AsRef<str> {
+
+        let mut nfa = nfa::Automaton::new();
+        let mut kinds = Vec::new();
+        for (index, (kind, regex_str, priority, ignore)) in rules.into_iter().enumerate() {
+            let regex_ast = regex::parse(regex_str.as_ref())
+                .map_err(|error| DynamicLexerError::Regex{ rule: index, error })?;
+            nfa.add_regex_with_accepting_value(&regex_ast, (priority, index));
+            kinds.push((kind, ignore));
+        }
+
+        // Determinize the state machine, remembering the first conflict
+        let mut conflict = None;
+        let dfa = dfa::Automaton::from_nfa(nfa, |a, b| {
+            if a.0 != b.0 {
+                std::cmp::max(a, b)
+            }
+            else {
+                conflict.get_or_insert((a.1.min(b.1), a.1.max(b.1)));
+                a
+            }
+        });
+        if let Some((first, second)) = conflict {
+            return Err(DynamicLexerError::Conflict{ first, second });
+        }
+
+        let dfa = dfa.minimize();
+        let bytes = dfa.to_utf8().minimize();
+        Ok(Self{
+            source: String::new(),
+            matcher: DynamicMatcher{ dfa, bytes, kinds, error, end },
+            longest_lookahead: Cell::new(0),
+        })
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Lexes the bytes without decoding them first. This is independent from
+    /// the source of the lexer and is not incremental. The invalid UTF-8
+    /// sequences become error tokens, the values of the tokens are converted
+    /// lossily.
+    pub fn iter_bytes<'a>(&'a self, source: &'a [u8]) -> ByteIter<'a, T> {
+        ByteIter::with_source(source, &self.matcher)
+    }
+}
+
+impl <T> Lexer for DynamicLexer<T> where T : Clone + PartialEq {
+    type TokenTag = T;
+
+    fn iter(&self) -> Iter<Self::TokenTag> {
+        Iter::with_source(&self.source, &self.matcher, &self.longest_lookahead)
+    }
+
+    fn modify(&mut self, tokens: &[Token<Self::TokenTag>], erased: Range<usize>, inserted: &str)
+        -> Modification<Self::TokenTag> {
+
+        relex(&mut self.source, &self.matcher, &self.longest_lookahead, tokens, erased, inserted)
+    }
+}
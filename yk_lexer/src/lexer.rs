@@ -2,10 +2,12 @@
  * Structures and traits for a lexer.
  */
 
+use std::cell::Cell;
 use std::marker::PhantomData;
 use std::ops::Range;
 use std::convert::TryFrom;
 use crate::position::Position;
+use crate::bytes::ByteIter;
 use crate::token::{TokenType, Token};
 
 /**
@@ -23,6 +25,102 @@ impl LexerState {
     pub fn new() -> Self {
         Self{ source_index: 0, position: Position::new(), last_char: None, }
     }
+
+    /// Steps the state over the next character of the source.
+    pub fn advance(&mut self, current_char: char) {
+        // Update the position
+        match (self.last_char, current_char) {
+            // Newlines
+              (Some('\r'), '\n')
+            | (Some('\r'), _)
+            | (_, '\n') => {
+                self.position.newline();
+            },
+
+            // Any other character
+            (_, ch) => {
+                if !ch.is_control() {
+                    self.position.advance_columns(1);
+                }
+            }
+        }
+        // Update the index
+        self.source_index += current_char.len_utf8();
+        // Update the last character
+        self.last_char = Some(current_char);
+    }
+
+    /// Steps the state over the next bytes of the source. Each invalid UTF-8
+    /// sequence counts as a single replacement character.
+    pub fn advance_bytes(&mut self, mut bytes: &[u8]) {
+        while !bytes.is_empty() {
+            // Most of the text is usually ASCII, which needs no validation
+            if bytes[0].is_ascii() {
+                self.advance(bytes[0] as char);
+                bytes = &bytes[1..];
+                continue;
+            }
+            let (valid, invalid_len) = match std::str::from_utf8(bytes) {
+                Ok(valid) => (valid, 0),
+                Err(err) => {
+                    let valid = std::str::from_utf8(&bytes[..err.valid_up_to()]).unwrap();
+                    (valid, err.error_len().unwrap_or(bytes.len() - valid.len()))
+                },
+            };
+            for current_char in valid.chars() {
+                self.advance(current_char);
+            }
+            if invalid_len > 0 {
+                let replacement = std::char::REPLACEMENT_CHARACTER;
+                self.advance(replacement);
+                self.source_index = self.source_index - replacement.len_utf8() + invalid_len;
+            }
+            bytes = &bytes[valid.len() + invalid_len..];
+        }
+    }
+}
+
+/**
+ * The logic that finds the next token in the source, the lexers are built on
+ * top of this.
+ */
+
+pub trait TokenMatcher {
+    type TokenTag;
+
+    /// Matches the next token from the given state. Returns the state after
+    /// the token, the token kind, None for ignored tokens, and the source
+    /// index the matcher looked at.
+    fn next_lexeme(&self, src: &str, state: &LexerState) -> (LexerState, Option<Self::TokenTag>, usize);
+
+    /// Matches the next token of the bytes, like 'next_lexeme'. By default
+    /// the bytes are decoded, so they must be valid UTF-8.
+    fn next_byte_lexeme(&self, src: &[u8], state: &LexerState) -> (LexerState, Option<Self::TokenTag>, usize) {
+        self.next_lexeme(std::str::from_utf8(src).expect("The source is not valid UTF-8!"), state)
+    }
+
+    fn is_end(&self, kind: &Self::TokenTag) -> bool;
+}
+
+/// The matcher of the lexers generated by the derive-macro.
+pub(crate) struct DerivedMatcher<T> {
+    phantom: PhantomData<T>,
+}
+
+impl <T> TokenMatcher for DerivedMatcher<T> where T : TokenType {
+    type TokenTag = T;
+
+    fn next_lexeme(&self, src: &str, state: &LexerState) -> (LexerState, Option<T>, usize) {
+        T::next_lexeme_internal(src, state)
+    }
+
+    fn next_byte_lexeme(&self, src: &[u8], state: &LexerState) -> (LexerState, Option<T>, usize) {
+        T::next_byte_lexeme_internal(src, state)
+    }
+
+    fn is_end(&self, kind: &T) -> bool {
+        kind.is_end()
+    }
 }
 
 /**
@@ -30,10 +128,13 @@ impl LexerState {
  */
 
 pub trait Lexer {
-    type TokenTag : TokenType;
+    type TokenTag;
 
     fn iter(&self) -> Iter<Self::TokenTag>;
 
+    /// Replaces the erased range of the source with the inserted text and
+    /// relexes the affected tokens. The tokens must have been produced by
+    /// this lexer, as it remembers how far they looked ahead.
     fn modify(&mut self, tokens: &[Token<Self::TokenTag>], erased: Range<usize>, inserted: &str)
         -> Modification<Self::TokenTag>;
 }
@@ -46,51 +147,65 @@ pub struct Iter<'a, T> {
     source: &'a str,
     state: LexerState,
     already_ended: bool,
-    phantom: PhantomData<T>,
+    matcher: &'a dyn TokenMatcher<TokenTag = T>,
+    // The longest lookahead of the returned tokens is recorded here
+    longest_lookahead: &'a Cell<usize>,
 }
 
 impl <'a, T> Iter<'a, T> {
-    fn with_source_and_state(source: &'a str, state: LexerState) -> Self {
-        Self{ source, state, already_ended: false, phantom: PhantomData, }
+    pub(crate) fn with_source_and_state(source: &'a str, state: LexerState,
+        matcher: &'a dyn TokenMatcher<TokenTag = T>, longest_lookahead: &'a Cell<usize>) -> Self {
+
+        Self{ source, state, already_ended: false, matcher, longest_lookahead, }
     }
 
-    fn with_source(source: &'a str) -> Self {
-        Self::with_source_and_state(source, LexerState::new())
+    pub(crate) fn with_source(source: &'a str, matcher: &'a dyn TokenMatcher<TokenTag = T>,
+        longest_lookahead: &'a Cell<usize>) -> Self {
+
+        Self::with_source_and_state(source, LexerState::new(), matcher, longest_lookahead)
     }
 }
 
-impl <'a, T> Iterator for Iter<'a, T> where T : TokenType {
+impl <'a, T> Iterator for Iter<'a, T> {
     type Item = Token<T>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        loop {
-            match T::next_lexeme_internal(self.source, &self.state) {
-                (state, Some(kind), mut lookahead) => {
-                    let range = self.state.source_index..state.source_index;
-                    lookahead -= range.end;
-                    let position = self.state.position;
-                    self.state = state;
-                    // If it's the end and we have already returned that, stop iteration
-                    if kind.is_end() {
-                        if self.already_ended {
-                            return None;
-                        }
-                        else {
-                            self.already_ended = true;
-                            let value = self.source[range.clone()].into();
-                            return Some(Token{ range, kind, position, lookahead, value });
-                        }
-                    }
-                    else {
-                        let value = self.source[range.clone()].into();
-                        return Some(Token{ range, kind, position, lookahead, value });
-                    }
-                },
+        let (source, matcher) = (self.source, self.matcher);
+        let token = next_token(&mut self.state, &mut self.already_ended, matcher,
+            |state| matcher.next_lexeme(source, state),
+            |range| source[range].into())?;
+        self.longest_lookahead.set(self.longest_lookahead.get().max(token.lookahead));
+        Some(token)
+    }
+}
 
-                (state, None, _) => {
-                    // Ignored token
-                    self.state = state;
+// The iteration shared by the text and the byte iterators. Skips the ignored
+// tokens and stops after returning the end token once.
+pub(crate) fn next_token<T, L, V>(state: &mut LexerState, already_ended: &mut bool,
+    matcher: &dyn TokenMatcher<TokenTag = T>, mut next_lexeme: L, value: V) -> Option<Token<T>>
+    where L : FnMut(&LexerState) -> (LexerState, Option<T>, usize), V : FnOnce(Range<usize>) -> String {
+
+    loop {
+        match next_lexeme(state) {
+            (next_state, Some(kind), lookahead) => {
+                let range = state.source_index..next_state.source_index;
+                let lookahead = lookahead - range.end;
+                let position = state.position;
+                *state = next_state;
+                // If it's the end and we have already returned that, stop iteration
+                if matcher.is_end(&kind) {
+                    if *already_ended {
+                        return None;
+                    }
+                    *already_ended = true;
                 }
+                let value = value(range.clone());
+                return Some(Token{ range, kind, position, lookahead, value });
+            },
+
+            (next_state, None, _) => {
+                // Ignored token
+                *state = next_state;
             }
         }
     }
@@ -127,69 +242,217 @@ pub struct AppliedModification {
     pub inserted: usize,
 }
 
+/**
+ * Incremental relexing, shared by the lexers.
+ */
+
+// TODO: We are ignoring position!
+// TODO: The actual lexing sould happen when the returned Modification is dropped
+// Similar to Drain iterator
+pub(crate) fn relex<T>(source: &mut String, matcher: &dyn TokenMatcher<TokenTag = T>, longest_lookahead: &Cell<usize>,
+    tokens: &[Token<T>], erased: Range<usize>, inserted: &str) -> Modification<T> where T : PartialEq {
+
+    // Modify the source string
+    // TODO: We could splice here
+    let erased_start = erased.start;
+    source.drain(erased.clone());
+    source.insert_str(erased_start, inserted);
+
+    // 'invalid' is the range of tokens that are definitely affected and removed
+    // This doesn't necessarily mean that this will be the only removed range
+    // as overriding tokens after that is still possible
+    let mut invalid = invalidated_range(tokens, &erased, longest_lookahead.get());
+    // How much the characters shifted from the source change
+    let offset = isize::try_from(inserted.len()).unwrap() - isize::try_from(erased.len()).unwrap();
+
+    // We start from the beginning of invalid
+    // We reconstruct a lexer state for that and start lexing until we are past the
+    // end of invalid territory and found an equivalent token
+    // If we are past the invalidation point but we find no equivalent token,
+    // we need to modify the invalidation range to include that token.
+
+    // Construct an initial state
+    let start_state = if invalid.start > 0 {
+        let last_tok = &tokens[invalid.start - 1];
+        let last_idx = last_tok.range.end;
+        let mut last_pos = last_tok.position;
+
+        // TODO: Doesn't work if the token can contain a newline!
+        last_pos.advance_columns(last_tok.range.len());
+
+        LexerState{
+            source_index: last_idx,
+            position: last_pos,
+            last_char: source[..last_idx].chars().rev().next(),
+        }
+    }
+    else {
+        LexerState::new()
+    };
+
+    // The index where we can count on equivalent state
+    let last_insertion = erased.start + inserted.len() + erased.len();
+
+    let mut inserted = Vec::new();
+
+    // Now we go until we hit an equivalent state
+    let mut it = Iter::with_source_and_state(source, start_state, matcher, longest_lookahead);
+    'outer: while let Some(token) = it.next() {
+        if token.range.start > last_insertion {
+            // Possibly an equivalent state
+            'inner: loop {
+                if invalid.end < tokens.len() {
+                    // Compare tokens
+                    let existing = &tokens[invalid.end];
+                    if token.range.end <= offset_number(existing.range.start, offset) {
+                        // We just insert, the new token is completely before the existing one
+                        inserted.push(token);
+                        break 'inner;
+                    }
+                    else {
+                        // The new token is after or intersects with the existing one
+                        // We need to check for equivalence
+                        // If equivalent, we are done
+                        // If not equivalent, we need to erase that token
+                        if equivalent_tokens(source, existing, &token, offset) {
+                            // Equivalent, we are done
+                            break 'outer;
+                        }
+                        else {
+                            // Not equivalent, erase
+                            invalid.end += 1;
+                        }
+                    }
+                }
+                else {
+                    // We are inserting at the end
+                    inserted.push(token);
+                    break 'inner;
+                }
+            }
+        }
+        else {
+            // No possibility of an equivalent state
+            'inner: loop {
+                if invalid.end < tokens.len() {
+                    // Compare tokens
+                    let existing = &tokens[invalid.end];
+                    if token.range.end <= offset_number(existing.range.start, offset) {
+                        // We just insert, the new token is completely before the existing one
+                        inserted.push(token);
+                        break 'inner;
+                    }
+                    else {
+                        // The new token is after or intersects with the existing one
+                        // We need to remove it
+                        invalid.end += 1;
+                    }
+                }
+                else {
+                    // We are inserting at the end
+                    inserted.push(token);
+                    break 'inner;
+                }
+            }
+        }
+    }
+
+    if it.already_ended {
+        invalid.end = tokens.len();
+    }
+
+    Modification{ erased: invalid, inserted, offset }
+}
+
+// The range of tokens affected by erasing the source range. The longest
+// lookahead of any token bounds how far back a token could have looked into
+// the erased range.
+fn invalidated_range<T>(tokens: &[Token<T>], erased: &Range<usize>, longest_lookahead: usize) -> Range<usize> {
+    let mut lower = match tokens.binary_search_by_key(&erased.start, |t| t.range.start) {
+        Ok(idx) | Err(idx) => idx,
+    };
+    let mut upper = match tokens[lower..].binary_search_by_key(&erased.end, |t| t.range.start) {
+        Ok(idx) | Err(idx) => idx,
+    } + lower;
+
+    if lower > 0 {
+        lower -= 1;
+    }
+    // Tokens further back could have looked into the erased range too,
+    // like the ones with a trailing context. Reaching the start of the
+    // range counts, as the token might have seen the end of the source.
+    // The ones in between don't necessarily look that far, so we walk back
+    // until not even the longest lookahead could reach the range.
+    let mut idx = lower;
+    while idx > 0 && tokens[idx - 1].range.end + longest_lookahead >= erased.start {
+        idx -= 1;
+        if tokens[idx].range.end + tokens[idx].lookahead >= erased.start {
+            lower = idx;
+        }
+    }
+    if upper < tokens.len() {
+        upper += 1;
+    }
+
+    lower..upper
+}
+
+fn equivalent_tokens<T>(src: &str, t1: &Token<T>, t2: &Token<T>, offs1: isize) -> bool where T : PartialEq {
+    let r1 = &t1.range;
+    let r1 = if offs1 > 0 {
+        let uo = usize::try_from(offs1).unwrap();
+        (r1.start + uo)..(r1.end + uo)
+    }
+    else {
+        let uo = usize::try_from(-offs1).unwrap();
+        (r1.start - uo)..(r1.end - uo)
+    };
+    let r2 = &t2.range;
+
+       r1.len() == r2.len()
+    && t1.lookahead == t2.lookahead
+    && t1.kind == t2.kind
+    && src[r1] == src[r2.clone()]
+}
+
+fn offset_number(u: usize, i: isize) -> usize {
+    if i > 0 {
+        let ui = usize::try_from(i).unwrap();
+        u + ui
+    }
+    else {
+        let ui = usize::try_from(-i).unwrap();
+        u - ui
+    }
+}
+
 /**
  * The builtin lexer.
  */
 
 pub struct StandardLexer<T> {
     source: String,
-    phantom: PhantomData<T>,
+    matcher: DerivedMatcher<T>,
+    longest_lookahead: Cell<usize>,
 }
 
 impl <T> StandardLexer<T> where T : PartialEq {
     pub fn new() -> Self {
-        Self{ source: String::new(), phantom: PhantomData, }
+        Self{ source: String::new(), matcher: DerivedMatcher{ phantom: PhantomData }, longest_lookahead: Cell::new(0), }
     }
 
     pub fn source(&self) -> &str {
         &self.source
     }
+}
 
-    fn invalidated_range(tokens: &[Token<T>], erased: &Range<usize>) -> Range<usize> {
-        let mut lower = match tokens.binary_search_by_key(&erased.start, |t| t.range.start) {
-            Ok(idx) | Err(idx) => idx,
-        };
-        let mut upper = match tokens[lower..].binary_search_by_key(&erased.end, |t| t.range.start) {
-            Ok(idx) | Err(idx) => idx,
-        } + lower;
-
-        if lower > 0 {
-            lower -= 1;
-        }
-        if upper < tokens.len() {
-            upper += 1;
-        }
-
-        lower..upper
-    }
-
-    fn equivalent_tokens(src: &str, t1: &Token<T>, t2: &Token<T>, offs1: isize) -> bool {
-        let r1 = &t1.range;
-        let r1 = if offs1 > 0 {
-            let uo = usize::try_from(offs1).unwrap();
-            (r1.start + uo)..(r1.end + uo)
-        }
-        else {
-            let uo = usize::try_from(-offs1).unwrap();
-            (r1.start - uo)..(r1.end - uo)
-        };
-        let r2 = &t2.range;
-
-           r1.len() == r2.len()
-        && t1.lookahead == t2.lookahead
-        && t1.kind == t2.kind
-        && src[r1] == src[r2.clone()]
-    }
-
-    fn offset_number(u: usize, i: isize) -> usize {
-        if i > 0 {
-            let ui = usize::try_from(i).unwrap();
-            u + ui
-        }
-        else {
-            let ui = usize::try_from(-i).unwrap();
-            u - ui
-        }
+impl <T> StandardLexer<T> where T : TokenType {
+    /// Lexes the bytes without decoding them first. This is independent from
+    /// the source of the lexer and is not incremental. The invalid UTF-8
+    /// sequences become error tokens, the values of the tokens are converted
+    /// lossily.
+    pub fn iter_bytes<'a>(&'a self, source: &'a [u8]) -> ByteIter<'a, T> {
+        ByteIter::with_source(source, &self.matcher)
     }
 }
 
@@ -197,124 +460,12 @@ impl <T> Lexer for StandardLexer<T> where T : TokenType + PartialEq {
     type TokenTag = T;
 
     fn iter(&self) -> Iter<Self::TokenTag> {
-        Iter::with_source(&self.source)
+        Iter::with_source(&self.source, &self.matcher, &self.longest_lookahead)
     }
 
-    // TODO: We are ignoring position!
-    // TODO: The actual lexing sould happen when the returned Modification is dropped
-    // Similar to Drain iterator
     fn modify(&mut self, tokens: &[Token<Self::TokenTag>], erased: Range<usize>, inserted: &str)
         -> Modification<Self::TokenTag> {
 
-        // Modify the source string
-        // TODO: We could splice here
-        let erased_start = erased.start;
-        self.source.drain(erased.clone());
-        self.source.insert_str(erased_start, inserted);
-
-        // 'invalid' is the range of tokens that are definitely affected and removed
-        // This doesn't necessarily mean that this will be the only removed range
-        // as overriding tokens after that is still possible
-        let mut invalid = Self::invalidated_range(tokens, &erased);
-        // How much the characters shifted from the source change
-        let offset = isize::try_from(inserted.len()).unwrap() - isize::try_from(erased.len()).unwrap();
-
-        // We start from the beginning of invalid
-        // We reconstruct a lexer state for that and start lexing until we are past the
-        // end of invalid territory and found an equivalent token
-        // If we are past the invalidation point but we find no equivalent token,
-        // we need to modify the invalidation range to include that token.
-
-        // Construct an initial state
-        let start_state = if invalid.start > 0 {
-            let last_tok = &tokens[invalid.start - 1];
-            let last_idx = last_tok.range.end;
-            let mut last_pos = last_tok.position;
-
-            // TODO: Doesn't work if the token can contain a newline!
-            last_pos.advance_columns(last_tok.range.len());
-
-            LexerState{
-                source_index: last_idx,
-                position: last_pos,
-                last_char: self.source[..last_idx].chars().rev().next(),
-            }
-        }
-        else {
-            LexerState::new()
-        };
-
-        // The index where we can count on equivalent state
-        let last_insertion = erased.start + inserted.len() + erased.len();
-
-        let mut inserted = Vec::new();
-
-        // Now we go until we hit an equivalent state
-        let mut it = Iter::<T>::with_source_and_state(&self.source, start_state);
-        'outer: while let Some(token) = it.next() {
-            if token.range.start > last_insertion {
-                // Possibly an equivalent state
-                'inner: loop {
-                    if invalid.end < tokens.len() {
-                        // Compare tokens
-                        let existing = &tokens[invalid.end];
-                        if token.range.end <= Self::offset_number(existing.range.start, offset) {
-                            // We just insert, the new token is completely before the existing one
-                            inserted.push(token);
-                            break 'inner;
-                        }
-                        else {
-                            // The new token is after or intersects with the existing one
-                            // We need to check for equivalence
-                            // If equivalent, we are done
-                            // If not equivalent, we need to erase that token
-                            if Self::equivalent_tokens(self.source(), existing, &token, offset) {
-                                // Equivalent, we are done
-                                break 'outer;
-                            }
-                            else {
-                                // Not equivalent, erase
-                                invalid.end += 1;
-                            }
-                        }
-                    }
-                    else {
-                        // We are inserting at the end
-                        inserted.push(token);
-                        break 'inner;
-                    }
-                }
-            }
-            else {
-                // No possibility of an equivalent state
-                'inner: loop {
-                    if invalid.end < tokens.len() {
-                        // Compare tokens
-                        let existing = &tokens[invalid.end];
-                        if token.range.end <= Self::offset_number(existing.range.start, offset) {
-                            // We just insert, the new token is completely before the existing one
-                            inserted.push(token);
-                            break 'inner;
-                        }
-                        else {
-                            // The new token is after or intersects with the existing one
-                            // We need to remove it
-                            invalid.end += 1;
-                        }
-                    }
-                    else {
-                        // We are inserting at the end
-                        inserted.push(token);
-                        break 'inner;
-                    }
-                }
-            }
-        }
-
-        if it.already_ended {
-            invalid.end = tokens.len();
-        }
-
-        Modification{ erased: invalid, inserted, offset }
+        relex(&mut self.source, &self.matcher, &self.longest_lookahead, tokens, erased, inserted)
     }
 }
@@ -8,11 +8,17 @@ extern crate yk_lexer_derive;
 mod position;
 mod lexer;
 mod token;
+mod dynamic;
+mod table;
+mod bytes;
 
 pub use yk_lexer_derive::Lexer;
 
 pub use position::Position;
-pub use lexer::{LexerState, Lexer, StandardLexer, Modification};
-pub use token::{TokenType, Token};
+pub use lexer::{LexerState, Lexer, StandardLexer, Modification, Iter, TokenMatcher};
+pub use token::{TokenType, Token, StateCounts};
+pub use dynamic::{DynamicLexer, DynamicLexerError};
+pub use table::{LexerTable, Accepting, NO_STATE};
+pub use bytes::{ByteTable, ByteIter};
 
 // TODO: Fuzz tester
@@ -0,0 +1,116 @@
+/**
+ * The runtime of the table-driven lexers. The derive-macro generates the
+ * tables instead of code for each state, which keeps large lexers small and
+ * quick to compile.
+ */
+
+use crate::lexer::LexerState;
+
+/// Splits the text matched with a trailing context between the given states,
+/// returning the state after the token itself
+pub(crate) type Split = fn(&str, &LexerState, &LexerState) -> Option<LexerState>;
+
+/// Marks the missing transitions in the transition table
+pub const NO_STATE: u32 = u32::MAX;
+
+/// What happens when the lexer enters a state
+pub enum Accepting<T : 'static> {
+    /// The state is not an accepting one
+    No,
+    /// The state accepts the token, None for ignored tokens
+    Token(Option<T>),
+    /// The state accepts the token if the matched text can be split with the
+    /// given function, because the token requires a trailing context
+    TrailingContext(Option<T>, fn(&str, &LexerState, &LexerState) -> Option<LexerState>),
+}
+
+/// The compressed tables of a lexer automaton. The characters are grouped
+/// into classes that behave the same in every state, and the states with the
+/// same transitions share a row.
+pub struct LexerTable<T : 'static> {
+    /// The class of each ASCII character
+    pub ascii_classes: [u32; 128],
+    /// The sorted, inclusive ranges of the other characters with their class
+    pub classes: &'static [(char, char, u32)],
+    /// The number of classes, which is the length of a row
+    pub class_count: usize,
+    /// The offset of the row for each state in the transition table
+    pub row_offsets: &'static [u32],
+    /// The rows after each other, the next state for each class
+    pub transitions: &'static [u32],
+    /// The action for each state
+    pub accepting: &'static [Accepting<T>],
+    pub start: u32,
+    pub error: T,
+    pub end: T,
+}
+
+impl <T> LexerTable<T> where T : Clone {
+    fn class_of(&self, current_char: char) -> usize {
+        if current_char.is_ascii() {
+            self.ascii_classes[current_char as usize] as usize
+        }
+        else {
+            // The ranges cover all non-ASCII characters
+            let idx = self.classes.partition_point(|(_, upper, _)| *upper < current_char);
+            self.classes[idx].2 as usize
+        }
+    }
+
+    /// Matches the next token, the same way as the code generated for each
+    /// state would.
+    pub fn next_lexeme(&self, src: &str, lex_state: &LexerState) -> (LexerState, Option<T>, usize) {
+        let mut current_state = self.start;
+
+        let mut last_accepting = None; // Option<(state, Option<token>)>
+        // The end of a later match with a trailing context, which is only
+        // split once the match is final
+        let mut last_trailing_context = None; // Option<(state, Option<token>, split)>
+        let mut first_lex_state = None; // Option<state>
+        let mut last_lex_state = lex_state.clone();
+
+        for current_char in src[lex_state.source_index..].chars() {
+            last_lex_state.advance(current_char);
+
+            // Save if first
+            if first_lex_state.is_none() {
+                first_lex_state = Some(last_lex_state.clone());
+            }
+
+            let offset = self.row_offsets[current_state as usize] as usize;
+            let next = self.transitions[offset + self.class_of(current_char)];
+            if next == NO_STATE {
+                break;
+            }
+            current_state = next;
+
+            match &self.accepting[current_state as usize] {
+                Accepting::No => {},
+                Accepting::Token(kind) => {
+                    last_accepting = Some((last_lex_state.clone(), kind.clone()));
+                    last_trailing_context = None;
+                },
+                Accepting::TrailingContext(kind, split) =>
+                    last_trailing_context = Some((last_lex_state.clone(), kind, split)),
+            }
+        }
+
+        // The whole match has a split, unless only an empty token could be
+        // split off, then the match before it counts
+        if let Some((end_state, kind, split)) = last_trailing_context {
+            if let Some(state) = split(src, lex_state, &end_state) {
+                return (state, kind.clone(), last_lex_state.source_index);
+            }
+        }
+
+        let lookahead = last_lex_state.source_index;
+        match (last_accepting, first_lex_state) {
+            // We succeeded before, return that
+            (Some((state, kind)), _) => (state, kind, lookahead),
+            // No success before, return an error
+            (None, Some(state)) => (state, Some(self.error.clone()), lookahead),
+            // Nothing consumed, no more characters, it's just the end on input
+            (None, None) => (lex_state.clone(), Some(self.end.clone()), lookahead),
+        }
+    }
+}
@@ -34,6 +34,17 @@ impl <T> Token<T> {
     }
 }
 
+/// The number of states in the automaton of a lexer, to keep an eye on the
+/// size of the generated code. The derive-macro returns these from an
+/// inherent `state_counts` function of the enum.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StateCounts {
+    /// After the subset construction
+    pub determinized: usize,
+    /// After minimization, this is the automaton the lexer runs
+    pub minimized: usize,
+}
+
 /// The type that the derive-macro implements on the user-defined enum.
 /// This is where the actual lexer logic is injected.
 pub trait TokenType : Sized {
@@ -43,4 +54,10 @@ pub trait TokenType : Sized {
 
     fn is_end(&self) -> bool;
     fn next_lexeme_internal(src: &str, state: &LexerState) -> (LexerState, Option<Self>, usize);
+
+    /// Matches the next token of the bytes. By default the bytes are decoded,
+    /// so they must be valid UTF-8, the derive-macro matches them directly.
+    fn next_byte_lexeme_internal(src: &[u8], state: &LexerState) -> (LexerState, Option<Self>, usize) {
+        Self::next_lexeme_internal(std::str::from_utf8(src).expect("The source is not valid UTF-8!"), state)
+    }
 }
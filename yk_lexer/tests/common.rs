@@ -1,3 +1,5 @@
+use yk_lexer::{Position, Token};
+
 
 pub fn assert_iter_eq<I1, I2>(mut i1: I1, mut i2: I2)
     where I1 : Iterator, I2 : Iterator,
@@ -12,3 +14,25 @@ pub fn assert_iter_eq<I1, I2>(mut i1: I1, mut i2: I2)
         }
     }
 }
+
+/// Lexes the source, then applies the edits one by one, checking after each
+/// one that the incrementally relexed tokens have the same ranges and kinds as
+/// the ones lexed from scratch.
+#[allow(dead_code)]
+pub fn assert_incremental<L>(lexer: &mut L, source: &str, edits: &[(std::ops::Range<usize>, &str)])
+    where L : yk_lexer::Lexer, L::TokenTag : PartialEq + std::fmt::Debug {
+    let mut tokens = Vec::new();
+    lexer.modify(&tokens, 0..0, source).apply(&mut tokens);
+    for (erased, inserted) in edits.iter().cloned() {
+        lexer.modify(&tokens, erased, inserted).apply(&mut tokens);
+        let relexed: Vec<_> = lexer.iter().collect();
+        assert_eq!(tokens.iter().map(|t| (&t.range, &t.kind)).collect::<Vec<_>>(),
+            relexed.iter().map(|t| (&t.range, &t.kind)).collect::<Vec<_>>());
+    }
+}
+
+/// A token on the first line, where the column is the character index.
+#[allow(dead_code)]
+pub fn token<T>(range: std::ops::Range<usize>, kind: T, column: usize, lookahead: usize, value: &str) -> Token<T> {
+    Token{ range, kind, position: Position{ line: 0, column }, lookahead, value: value.into() }
+}
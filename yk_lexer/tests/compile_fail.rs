@@ -0,0 +1,8 @@
+
+// The misused attributes are reported as compile errors at their place, the
+// expected messages are next to the sources in 'tests/ui'
+#[test]
+fn compile_errors() {
+    let cases = trybuild::TestCases::new();
+    cases.compile_fail("tests/ui/*.rs");
+}
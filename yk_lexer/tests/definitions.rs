@@ -0,0 +1,193 @@
+
+mod common;
+
+use yk_lexer::{Token, TokenType, Lexer};
+use common::{assert_iter_eq, token};
+
+/**
+ * Definition options.
+ */
+
+#[derive(Lexer, Clone, PartialEq, Eq, Debug)]
+#[dot_file = "target/yk_lexer_dot/Sql.dot"]
+enum Sql {
+    #[error] Error,
+    #[end] End,
+    #[regex(r" ")] Space,
+    #[c_ident] Ident,
+    #[token("select", case_insensitive)] KwSelect,
+    #[regex(r"@x[a-c]+", case_insensitive)] Hex,
+}
+
+#[test]
+fn case_insensitive() {
+    let mut lexer = Sql::lexer();
+    lexer.modify(&[], 0..0, "SeLeCt selects @XaBC");
+    assert_iter_eq([
+        token(0..6, Sql::KwSelect, 0, 1, "SeLeCt"),
+        token(6..7, Sql::Space, 6, 1, " "),
+        token(7..14, Sql::Ident, 7, 1, "selects"),
+        token(14..15, Sql::Space, 14, 1, " "),
+        token(15..20, Sql::Hex, 15, 0, "@XaBC"),
+        token(20..20, Sql::End, 20, 0, ""),
+    ].iter().cloned(), lexer.iter());
+}
+
+#[test]
+fn dot_file() {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/target/yk_lexer_dot/Sql.dot");
+    let dot = std::fs::read_to_string(path).unwrap();
+    assert!(dot.contains("KwSelect"));
+    assert!(dot.contains("Hex"));
+}
+
+/**
+ * Regex fragments.
+ */
+
+#[derive(Lexer, Clone, PartialEq, Eq, Debug)]
+#[define(DIGIT = "[0-9]", EXP = "[eE][+-]?{DIGIT}+")]
+#[define(FLOAT = "{DIGIT}+\\.{DIGIT}*{EXP}?|{DIGIT}+{EXP}")]
+enum Number {
+    #[error] Error,
+    #[end] End,
+    #[regex(r" ")] Space,
+    #[regex(r"{DIGIT}+")] IntLit,
+    #[regex(r"{FLOAT}")] FloatLit,
+    #[regex(r"{DIGIT}+((?i)u)")] UnsignedLit,
+}
+
+#[test]
+fn nested_fragments() {
+    let mut lexer = Number::lexer();
+    lexer.modify(&[], 0..0, "12 1.5e-3 7E2 3. 4U 5e");
+    assert_iter_eq([
+        token(0..2, Number::IntLit, 0, 1, "12"),
+        token(2..3, Number::Space, 2, 1, " "),
+        token(3..9, Number::FloatLit, 3, 1, "1.5e-3"),
+        token(9..10, Number::Space, 9, 1, " "),
+        token(10..13, Number::FloatLit, 10, 1, "7E2"),
+        token(13..14, Number::Space, 13, 1, " "),
+        token(14..16, Number::FloatLit, 14, 1, "3."),
+        token(16..17, Number::Space, 16, 1, " "),
+        token(17..19, Number::UnsignedLit, 17, 1, "4U"),
+        token(19..20, Number::Space, 19, 1, " "),
+        token(20..21, Number::IntLit, 20, 1, "5"),
+        token(21..22, Number::Error, 21, 0, "e"),
+        token(22..22, Number::End, 22, 0, ""),
+    ].iter().cloned(), lexer.iter());
+}
+
+/**
+ * Trailing context.
+ */
+
+#[derive(Lexer, Clone, PartialEq, Eq, Debug)]
+enum Span {
+    #[error] Error,
+    #[end] End,
+    #[regex(r"[ \n]")] Ws,
+    #[c_ident] Ident,
+    #[regex(r"[0-9]+", followed_by = r"\.\.")] RangeStart,
+    #[regex(r"[0-9]+(\.[0-9]+)?")] Number,
+    #[token("..")] DotDot,
+    #[token(".")] Dot,
+    #[regex(r"/\*~(.*\*/.*)\*/")] Comment,
+}
+
+fn lex(source: &str) -> Vec<Token<Span>> {
+    let mut lexer = Span::lexer();
+    lexer.modify(&[], 0..0, source);
+    lexer.iter().collect()
+}
+
+// Compares everything, except the positions, as those are not updated by
+// the incremental relexing
+fn relevant(tokens: &[Token<Span>]) -> Vec<(std::ops::Range<usize>, Span, usize)> {
+    tokens.iter().map(|t| (t.range.clone(), t.kind.clone(), t.lookahead)).collect()
+}
+
+#[test]
+fn trailing_context() {
+    assert_eq!(relevant(&lex("1..2")), vec![
+        (0..1, Span::RangeStart, 3),
+        (1..3, Span::DotDot, 1),
+        (3..4, Span::Number, 0),
+        (4..4, Span::End, 0),
+    ]);
+    assert_eq!(relevant(&lex("1.5")), vec![
+        (0..3, Span::Number, 0),
+        (3..3, Span::End, 0),
+    ]);
+    assert_eq!(relevant(&lex("12..")), vec![
+        (0..2, Span::RangeStart, 2),
+        (2..4, Span::DotDot, 0),
+        (4..4, Span::End, 0),
+    ]);
+}
+
+#[test]
+fn trailing_context_appears() {
+    let mut lexer = Span::lexer();
+    let mut tokens = Vec::new();
+    lexer.modify(&tokens, 0..0, "1.x").apply(&mut tokens);
+    assert_eq!(relevant(&tokens), vec![
+        (0..1, Span::Number, 2),
+        (1..2, Span::Dot, 1),
+        (2..3, Span::Ident, 0),
+        (3..3, Span::End, 0),
+    ]);
+    lexer.modify(&tokens, 2..3, ".").apply(&mut tokens);
+    assert_eq!(relevant(&tokens), relevant(&lex("1..")));
+    assert_eq!(tokens[0].kind, Span::RangeStart);
+}
+
+#[test]
+fn modification_seen_by_earlier_token() {
+    // The error token '/' looks until the end, searching for the end of the
+    // comment, but the tokens after it don't
+    let mut lexer = Span::lexer();
+    let mut tokens = Vec::new();
+    lexer.modify(&tokens, 0..0, "/*i \n").apply(&mut tokens);
+    lexer.modify(&tokens, 4..5, "1*/").apply(&mut tokens);
+    assert_eq!(relevant(&tokens), relevant(&lex("/*i 1*/")));
+    assert_eq!(tokens[0].kind, Span::Comment);
+}
+
+// Every letter ends a match with the nullable trailing context, which must not
+// make long words slow to lex
+#[derive(Lexer, Clone, PartialEq, Eq, Debug)]
+enum Nullable {
+    #[error] Error,
+    #[end] End,
+    #[regex(r"[a-z]+", followed_by = r"b*")] Word,
+    #[regex(r";")] Semicolon,
+}
+
+#[derive(Lexer, Clone, PartialEq, Eq, Debug)]
+#[table_driven]
+enum TableNullable {
+    #[error] Error,
+    #[end] End,
+    #[regex(r"[a-z]+", followed_by = r"b*")] Word,
+    #[regex(r";")] Semicolon,
+}
+
+#[test]
+fn nullable_trailing_context() {
+    let source = format!("{};ab", "a".repeat(100_000));
+    let mut lexer = Nullable::lexer();
+    lexer.modify(&[], 0..0, &source);
+    let kinds: Vec<_> = lexer.iter().map(|t| (t.range, t.kind)).collect();
+    assert_eq!(kinds, vec![
+        (0..100_000, Nullable::Word),
+        (100_000..100_001, Nullable::Semicolon),
+        (100_001..100_003, Nullable::Word),
+        (100_003..100_003, Nullable::End),
+    ]);
+
+    let mut table_lexer = TableNullable::lexer();
+    table_lexer.modify(&[], 0..0, &source);
+    let table_ranges: Vec<_> = table_lexer.iter().map(|t| t.range).collect();
+    assert_eq!(table_ranges, kinds.into_iter().map(|(range, _)| range).collect::<Vec<_>>());
+}
@@ -0,0 +1,139 @@
+
+mod common;
+
+use yk_lexer::{Position, Lexer, DynamicLexer, DynamicLexerError};
+use common::{assert_iter_eq, assert_incremental, token};
+
+const ERROR: usize = 0;
+const END: usize = 1;
+const WS: usize = 2;
+const IDENT: usize = 3;
+const INT_LIT: usize = 4;
+const KW_IF: usize = 5;
+const COMMENT: usize = 6;
+
+fn lexer() -> DynamicLexer {
+    DynamicLexer::new(vec![
+        (WS, r"[ \t\r\n]", 0, true),
+        (IDENT, r"[[:alpha:]_]\w*", 0, false),
+        (INT_LIT, r"[0-9]+", 0, false),
+        (KW_IF, r"if", 1, false),
+    ], ERROR, END).unwrap()
+}
+
+#[test]
+fn lex_empty() {
+    let lexer = lexer();
+    assert_iter_eq([
+        token(0..0, END, 0, 0, ""),
+    ].iter().cloned(), lexer.iter());
+}
+
+#[test]
+fn lex_tokens() {
+    let mut lexer = lexer();
+    lexer.modify(&[], 0..0, "if iffy 12$");
+    assert_iter_eq([
+        token(0..2, KW_IF, 0, 1, "if"),
+        token(3..7, IDENT, 3, 1, "iffy"),
+        token(8..10, INT_LIT, 8, 1, "12"),
+        token(10..11, ERROR, 10, 0, "$"),
+        token(11..11, END, 11, 0, ""),
+    ].iter().cloned(), lexer.iter());
+}
+
+#[test]
+fn lex_newlines() {
+    let mut lexer = lexer();
+    lexer.modify(&[], 0..0, "a\r\nb");
+    let positions: Vec<_> = lexer.iter().map(|t| t.position).collect();
+    assert_eq!(positions, vec![Position{ line: 0, column: 0 }, Position{ line: 1, column: 0 }, Position{ line: 1, column: 1 }]);
+}
+
+#[test]
+fn custom_token_kinds() {
+    let mut lexer = DynamicLexer::new(vec![
+        ("word", r"[a-z]+", 0, false),
+        ("space", r" +", 0, true),
+    ], "error", "end").unwrap();
+    lexer.modify(&[], 0..0, "ab cd");
+    let kinds: Vec<_> = lexer.iter().map(|t| t.kind).collect();
+    assert_eq!(kinds, vec!["word", "word", "end"]);
+}
+
+#[test]
+fn invalid_regex() {
+    let err = DynamicLexer::new(vec![(0, r"a", 0, false), (1, r"(b", 0, false)], 2, 3).err().unwrap();
+    match err {
+        DynamicLexerError::Regex{ rule, error } => assert_eq!((rule, error.position), (1, 2)),
+        err => panic!("Unexpected error {:?}!", err),
+    }
+}
+
+#[test]
+fn conflicting_rules() {
+    let err = DynamicLexer::new(vec![(0, r"[a-z]+", 0, false), (1, r"\d", 0, false), (2, r"if", 0, false)], 3, 4).err();
+    assert_eq!(err, Some(DynamicLexerError::Conflict{ first: 0, second: 2 }));
+}
+
+#[test]
+fn incremental_modification() {
+    let edits = [(3..4, "iffy"), (0..2, "i"), (4..4, " 3"), (0..1, ""), (8..9, "f")];
+    assert_incremental(&mut lexer(), "if x 12", &edits);
+}
+
+#[test]
+fn modification_seen_by_earlier_token() {
+    // The error token '/' looks until the end, searching for the end of the
+    // comment, but the tokens after it don't
+    let mut lexer = DynamicLexer::new(vec![
+        (WS, r"[ \n]", 0, true),
+        (IDENT, r"[a-z]+", 0, false),
+        (COMMENT, r"/\*~(.*\*/.*)\*/", 0, false),
+    ], ERROR, END).unwrap();
+    let mut tokens = Vec::new();
+    lexer.modify(&tokens, 0..0, "/*i \n").apply(&mut tokens);
+    lexer.modify(&tokens, 4..5, "1*/").apply(&mut tokens);
+    assert_iter_eq(tokens.into_iter(), lexer.iter());
+}
+
+#[test]
+fn lex_bytes() {
+    let mut lexer = lexer();
+    let source = "if iffy\r\n12 $é x";
+    lexer.modify(&[], 0..0, source);
+    assert_iter_eq(lexer.iter(), lexer.iter_bytes(source.as_bytes()));
+}
+
+#[test]
+fn lex_invalid_utf8() {
+    // Invalid and truncated sequences, then valid characters no rule matches
+    let lexer = lexer();
+    assert_iter_eq([
+        token(0..1, IDENT, 0, 1, "a"),
+        token(1..2, ERROR, 1, 0, "\u{FFFD}"),
+        token(2..4, INT_LIT, 2, 2, "12"),
+        token(4..6, ERROR, 4, 0, "\u{FFFD}"),
+        token(7..10, ERROR, 6, 0, "€"),
+        token(10..12, ERROR, 7, 0, "é"),
+        token(12..14, ERROR, 8, 0, "\u{FFFD}"),
+        token(14..14, END, 9, 0, ""),
+    ].iter().cloned(), lexer.iter_bytes(b"a\xFF12\xE2\x82 \xE2\x82\xAC\xC3\xA9\xF0\x9F"));
+}
+
+#[test]
+fn modification_seen_by_distant_token() {
+    // Many short tokens separate the unterminated comment from the edit, the
+    // walk back must not stop at them
+    let mut lexer = DynamicLexer::new(vec![
+        (WS, r"[ \n]", 0, true),
+        (IDENT, r"[a-z]+", 0, false),
+        (COMMENT, r"/\*~(.*\*/.*)\*/", 0, false),
+    ], ERROR, END).unwrap();
+    let source = format!("x /*{}\n", "ab ".repeat(100));
+    let mut tokens = Vec::new();
+    lexer.modify(&tokens, 0..0, &source).apply(&mut tokens);
+    lexer.modify(&tokens, source.len() - 1..source.len(), "*/").apply(&mut tokens);
+    assert_eq!(tokens.iter().map(|t| (&t.range, t.kind)).collect::<Vec<_>>(),
+        vec![(&(0..1), IDENT), (&(2..source.len() + 1), COMMENT), (&(source.len() + 1..source.len() + 1), END)]);
+}
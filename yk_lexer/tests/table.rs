@@ -0,0 +1,97 @@
+
+mod common;
+
+use yk_lexer::{Lexer, TokenType};
+use common::{assert_iter_eq, assert_incremental, token};
+
+// The same tokens for both backends, with trailing contexts and non-ASCII
+// characters
+macro_rules! token_kind {
+    ($name:ident $(#[$backend:meta])*) => {
+        #[derive(Lexer, Clone, PartialEq, Eq, Debug)]
+        $(#[$backend])*
+        enum $name {
+            #[error] Error,
+            #[end] End,
+            #[regex(r"[ \t\r\n]+")] Ws,
+            #[c_ident] Ident,
+            #[regex(r"[0-9]+")] IntLit,
+            #[regex(r"[0-9]+", followed_by = r"\.\.")] RangeStart,
+            #[token("..")] Range,
+            #[token(".")] Dot,
+            #[token("if")] KwIf,
+            #[regex(r"'[^']*'")] StrLit,
+            #[regex(r"/\*~(.*\*/.*)\*/")] Comment,
+        }
+    };
+}
+
+token_kind!(MatchKind);
+token_kind!(TableKind #[table_driven]);
+
+// Lexes the source with both backends, comparing everything but the kinds by
+// their names. Lexing the bytes must give the same tokens too.
+fn assert_same_tokens(source: &str) {
+    let mut match_lexer = MatchKind::lexer();
+    match_lexer.modify(&[], 0..0, source);
+    let mut table_lexer = TableKind::lexer();
+    table_lexer.modify(&[], 0..0, source);
+    assert_iter_eq(match_lexer.iter(), match_lexer.iter_bytes(source.as_bytes()));
+    assert_iter_eq(table_lexer.iter(), table_lexer.iter_bytes(source.as_bytes()));
+
+    let describe = |kind: String, range, position, lookahead, value| (kind, range, position, lookahead, value);
+    assert_iter_eq(
+        match_lexer.iter().map(|t| describe(format!("{:?}", t.kind), t.range, t.position, t.lookahead, t.value)),
+        table_lexer.iter().map(|t| describe(format!("{:?}", t.kind), t.range, t.position, t.lookahead, t.value)));
+}
+
+#[test]
+fn table_tokens() {
+    let mut lexer = TableKind::lexer();
+    lexer.modify(&[], 0..0, "if iffy 1..é");
+    assert_iter_eq([
+        token(0..2, TableKind::KwIf, 0, 1, "if"),
+        token(2..3, TableKind::Ws, 2, 1, " "),
+        token(3..7, TableKind::Ident, 3, 1, "iffy"),
+        token(7..8, TableKind::Ws, 7, 1, " "),
+        token(8..9, TableKind::RangeStart, 8, 4, "1"),
+        token(9..11, TableKind::Range, 9, 2, ".."),
+        token(11..13, TableKind::Error, 11, 0, "é"),
+        token(13..13, TableKind::End, 12, 0, ""),
+    ].iter().cloned(), lexer.iter());
+}
+
+#[test]
+fn same_as_match_backend() {
+    assert_same_tokens("");
+    assert_same_tokens("if iffy 12 $ x1");
+    assert_same_tokens("1..2. 3.. ..4");
+    assert_same_tokens("'h\u{E001}ié€' /* a\n*/ 'unterminated é");
+    assert_same_tokens("/* unterminated\n if 1..");
+}
+
+#[test]
+fn same_state_counts() {
+    assert_eq!(MatchKind::state_counts(), TableKind::state_counts());
+}
+
+#[test]
+fn incremental_modification() {
+    let edits = [(3..4, "iffy"), (0..2, "i"), (4..4, " 3.."), (0..1, "/*"), (8..9, "*/")];
+    assert_incremental(&mut TableKind::lexer(), "if x 12", &edits);
+}
+
+#[test]
+fn lex_invalid_utf8() {
+    // Invalid and truncated sequences between the tokens, the trailing context
+    // still applies
+    let lexer = TableKind::lexer();
+    assert_iter_eq([
+        token(0..1, TableKind::RangeStart, 0, 3, "1"),
+        token(1..3, TableKind::Range, 1, 1, ".."),
+        token(3..4, TableKind::Error, 3, 0, "\u{FFFD}"),
+        token(4..6, TableKind::Ident, 4, 2, "ab"),
+        token(6..8, TableKind::Error, 6, 0, "\u{FFFD}"),
+        token(8..8, TableKind::End, 7, 0, ""),
+    ].iter().cloned(), lexer.iter_bytes(b"1..\xFFab\xE2\x82"));
+}
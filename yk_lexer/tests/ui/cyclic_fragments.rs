@@ -0,0 +1,11 @@
+use yk_lexer::Lexer;
+
+#[derive(Lexer, Clone, PartialEq, Debug)]
+#[define(A = "a{B}?", B = "b{C}", C = "c{A}")]
+enum TokenKind {
+    #[error] Error,
+    #[end] End,
+    #[regex("{A}")] Word,
+}
+
+fn main() {}
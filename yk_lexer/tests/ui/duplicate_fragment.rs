@@ -0,0 +1,12 @@
+use yk_lexer::Lexer;
+
+#[derive(Lexer, Clone, PartialEq, Debug)]
+#[define(DIGIT = "[0-9]")]
+#[define(DIGIT = "[0-7]")]
+enum TokenKind {
+    #[error] Error,
+    #[end] End,
+    #[regex("{DIGIT}+")] IntLit,
+}
+
+fn main() {}
@@ -0,0 +1,11 @@
+use yk_lexer::Lexer;
+
+#[derive(Lexer, Clone, PartialEq, Debug)]
+#[define(DIGIT = "[0-9]")]
+enum TokenKind {
+    #[error] Error,
+    #[end] End,
+    #[regex("{DIGIT}+{UNDEF}")] IntLit,
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use yk_lexer::Lexer;
+
+#[derive(Lexer, Clone, PartialEq, Debug)]
+enum TokenKind {
+    #[error] Error,
+    #[end] End,
+    #[token("select", case_insenstive)] KwSelect,
+}
+
+fn main() {}
@@ -7,9 +7,14 @@ extern crate proc_macro;
 extern crate yk_dense_fsa;
 extern crate syn;
 extern crate quote;
+extern crate proc_macro2;
 
+use std::cell::RefCell;
+use std::collections::HashMap;
 use proc_macro::TokenStream;
-use syn::{parse_macro_input, ItemEnum, LitStr, Fields, Ident};
+use syn::{parse_macro_input, ItemEnum, LitStr, Fields, Ident, Attribute, Token};
+use syn::parse::ParseStream;
+use proc_macro2::Span;
 use quote::quote;
 use yk_dense_fsa::{nfa, dfa};
 use yk_dense_fsa::yk_regex_parse as regex;
@@ -18,7 +23,7 @@ use yk_dense_fsa::yk_intervals::{LowerBound, UpperBound};
 // Identifier for the front-end lexer library
 const FRONT_LIBRARY_NAME: &str = "yk_lexer";
 // Regular expression for a C-style identifier
-const C_IDENT_REGEX: &str = "[A-Za-z_][A-Za-z0-9_]*";
+const C_IDENT_REGEX: &str = r"[[:alpha:]_]\w*";
 // Attribute name for error
 const ATTRIBUTE_ERR: &str = "error";
 // Attribute name for end
@@ -31,28 +36,77 @@ const ATTRIBUTE_REGEX: &str = "regex";
 const ATTRIBUTE_TOKEN: &str = "token";
 // Attribute to ignore the defined token
 const ATTRIBUTE_IGNORE: &str = "ignore";
+// Attribute on the enum to define named regex fragments
+const ATTRIBUTE_DEFINE: &str = "define";
+// Attribute on the enum to generate transition tables instead of match arms
+const ATTRIBUTE_TABLE_DRIVEN: &str = "table_driven";
+// Option for token and regex definitions to match regardless of case
+const OPTION_CASE_INSENSITIVE: &str = "case_insensitive";
+// Option for token and regex definitions to require a trailing context
+const OPTION_FOLLOWED_BY: &str = "followed_by";
+// Attribute on the enum naming a file to write the lexer automaton into, for
+// debugging. Relative paths are relative to the crate's manifest directory.
+const ATTRIBUTE_DOT_FILE: &str = "dot_file";
+// Environment variable naming a directory to write the lexer automata into as
+// '<EnumName>.dot', for debugging. Changing it doesn't trigger a rebuild.
+const DOT_DIRECTORY_VARIABLE: &str = "YK_LEXER_DOT_DIR";
 
 struct TokenDefinition {
     variant_ident: Ident,
     regex_str: String,
+    // The literal the regex came from, for error reporting
+    regex_lit: Option<LitStr>,
+    case_insensitive: bool,
+    // The regex that must follow the token without being part of it
+    followed_by: Option<LitStr>,
     precedence: usize,
     ignore: bool,
 }
 
+// The arguments of a token or regex definition
+struct DefinitionArgs {
+    lit: LitStr,
+    case_insensitive: bool,
+    followed_by: Option<LitStr>,
+}
+
+// A named regex fragment, referenced as {NAME} in the token regexes
+struct Fragment {
+    name: Ident,
+    regex_lit: LitStr,
+}
+
 struct LexerData {
     enum_name: Ident,
     err_variant: Ident,
     end_variant: Ident,
     tokens: Vec<TokenDefinition>,
+    fragments: Vec<Fragment>,
+    table_driven: bool,
+    dot_file: Option<LitStr>,
 }
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq)]
 struct AcceptingState {
     variant_ident: Ident,
+    // The simplified regex, for diagnostics
+    regex: String,
     precedence: usize,
     ignore: bool,
+    // Index of the trailing context matchers, if the token has one
+    trailing_context: Option<usize>,
 }
 
+/// Derives a lexer for the token enum. Besides the variant attributes, the enum
+/// itself accepts:
+///  * `#[define(NAME = "regex", ...)]` to define regex fragments usable as `{NAME}`,
+///  * `#[table_driven]` to match with a transition table instead of code,
+///  * `#[dot_file = "path"]` to write the automaton into a DOT file on every
+///    build, relative to the crate's manifest directory.
+///
+/// Setting the `YK_LEXER_DOT_DIR` environment variable also writes the automata
+/// into that directory, but only for the crates that get recompiled afterwards,
+/// so touch the crate or run `cargo clean -p <crate>` after setting it.
 #[proc_macro_derive(Lexer, attributes(
     error,
     end,
@@ -60,6 +114,9 @@ struct AcceptingState {
     regex,
     token,
     ignore,
+    define,
+    table_driven,
+    dot_file,
 ))]
 pub fn yk_lexer(item: TokenStream) -> TokenStream {
     // Identifier for the front-end lexer library
@@ -67,17 +124,59 @@ pub fn yk_lexer(item: TokenStream) -> TokenStream {
 
     // Parse the enum
     let enm = parse_macro_input!(item as ItemEnum);
-    let lexer_data = parse_attributes(&enm);
+    let lexer_data = match parse_attributes(&enm) {
+        Ok(lexer_data) => lexer_data,
+        Err(err) => return err.to_compile_error().into(),
+    };
 
     let enum_name = lexer_data.enum_name;
     let error_token = lexer_data.err_variant;
     let end_token = lexer_data.end_variant;
 
+    // The fragments are resolved for each set of flags they are used with,
+    // erroneous ones are reported even if they are unused
+    let mut fragment_sets = Vec::new();
+    if let Err(err) = fragments_for(&lexer_data.fragments, regex::Flags::default(), &mut fragment_sets) {
+        return err.to_compile_error().into();
+    }
+
     // Now we have the regexes, let's construct a DFA
     let mut nfa = nfa::Automaton::new();
-    for TokenDefinition{ variant_ident, regex_str, precedence, ignore } in lexer_data.tokens {
-        let regex_ast = regex::parse(&regex_str).expect("Error in regex syntax!"); // TODO: Good error msg
-        nfa.add_regex_with_accepting_value(&regex_ast, AcceptingState{ variant_ident, precedence, ignore });
+    // The separate matchers for the tokens with a trailing context
+    let mut trailing_contexts = Vec::new();
+    for definition in lexer_data.tokens {
+        let TokenDefinition{ variant_ident, regex_str, regex_lit, case_insensitive, followed_by, precedence, ignore } = definition;
+        let flags = regex::Flags{ case_insensitive, ..regex::Flags::default() };
+        let span = regex_lit.map(|lit| lit.span()).unwrap_or_else(|| variant_ident.span());
+        let fragments = match fragments_for(&lexer_data.fragments, flags, &mut fragment_sets) {
+            Ok(fragments) => fragments,
+            Err(err) => return err.to_compile_error().into(),
+        };
+        let regex_ast = match parse_regex(&regex_str, flags, fragments, span) {
+            Ok(ast) => ast,
+            Err(err) => return err.to_compile_error().into(),
+        };
+
+        // With a trailing context the whole text is matched like with flex's
+        // 'r/s', so it counts for the longest match. The separate automata
+        // determine where the token itself ends.
+        let (regex_ast, trailing_context) = match followed_by {
+            Some(context) => {
+                let context_ast = match parse_regex(&context.value(), flags, fragments, context.span()) {
+                    Ok(ast) => ast,
+                    Err(err) => return err.to_compile_error().into(),
+                };
+                let prefix_dfa: dfa::Automaton<char> = nfa::Automaton::from(regex_ast.clone()).into();
+                let context_dfa: dfa::Automaton<char> = nfa::Automaton::from(context_ast.clone()).into();
+                trailing_contexts.push((prefix_dfa.minimize(), context_dfa.minimize()));
+                let whole = regex::Node::Sequence{ first: Box::new(regex_ast), second: Box::new(context_ast) };
+                (regex::simplify(whole), Some(trailing_contexts.len() - 1))
+            },
+            None => (regex_ast, None),
+        };
+
+        let regex = regex_ast.to_string();
+        nfa.add_regex_with_accepting_value(&regex_ast, AcceptingState{ variant_ident, regex, precedence, ignore, trailing_context });
     }
 
     // Determinize the state machine
@@ -89,94 +188,120 @@ pub fn yk_lexer(item: TokenStream) -> TokenStream {
             b
         }
         else {
-            panic!("{} and {} are conflicting!", a.variant_ident, b.variant_ident);
+            panic!("{} ({}) and {} ({}) are conflicting!", a.variant_ident, a.regex, b.variant_ident, b.regex);
         }
     });
-
-    // We collect each arm of the match
-    let mut state_transitions = Vec::new();
-    for state in dfa.states() {
-        let mut arms = Vec::new();
-
-        // We visit the state's possible transitions
-        if let Some(transitions) = dfa.transitions_from(&state) {
-            for (interval, destination) in transitions {
-                // We need to generate an arm
-                let lower = to_lower_inclusive_u32(&interval.lower);
-                let upper = to_upper_inclusive_u32(&interval.upper);
-
-                if lower > upper {
-                    // We need this because there can be ranges like ('o'; 'p')
-                    // which turns out to be x+1..=x in inclusive form
-                    // This is potentially completely legal in the interval library
-                    // but doesn't make sense here, as it's empty.
-                    continue;
-                }
-
-                let arm_pattern = match (lower, upper) {
-                    (Some(a), Some(b)) => quote!{ #a..=#b },
-                    (Some(a), None) => quote!{ #a.. },
-                    (None, Some(b)) => quote!{ ..=#b },
-                    (None, None) => quote!{ .. },
-                };
-
-                // Build a "save" statement if the state is an accepting one
-                let acceptor = match dfa.accepting_value(&destination) {
-                    Some(AcceptingState{ variant_ident, precedence: _, ignore: false }) => quote!{
-                        last_accepting = Some((last_lex_state.clone(), Some(#enum_name::#variant_ident)))
-                    },
-                    Some(AcceptingState{ variant_ident: _, precedence: _, ignore: true }) => quote!{
-                        last_accepting = Some((last_lex_state.clone(), None))
-                    },
-                    None => quote!{},
-                };
-
-                // Build the actual match arm
-                let destination_id = destination.id();
-                let arm = quote!{
-                    #arm_pattern => {
-                        current_state = #destination_id;
-                        #acceptor;
-                    },
-                };
-
-                arms.push(arm);
+    // Every state becomes a match arm or a table row, so it's worth making
+    // them as few as possible
+    let determinized_states = dfa.state_count();
+    let dfa = dfa.minimize();
+    let minimized_states = dfa.state_count();
+
+    // The requested automaton dumps, with the span to report the errors at
+    let mut dot_paths = Vec::new();
+    if let Some(dot_file) = &lexer_data.dot_file {
+        let manifest_directory = std::env::var("CARGO_MANIFEST_DIR").unwrap_or_default();
+        dot_paths.push((std::path::Path::new(&manifest_directory).join(dot_file.value()), dot_file.span()));
+    }
+    if let Ok(directory) = std::env::var(DOT_DIRECTORY_VARIABLE) {
+        dot_paths.push((std::path::Path::new(&directory).join(format!("{}.dot", enum_name)), enum_name.span()));
+    }
+    if !dot_paths.is_empty() {
+        let dot = dfa.to_dot(|accepting| {
+            if accepting.ignore { format!("{} (ignored)", accepting.variant_ident) }
+            else { accepting.variant_ident.to_string() }
+        });
+        for (path, span) in dot_paths {
+            let written = match path.parent() {
+                Some(parent) => std::fs::create_dir_all(parent).and_then(|_| std::fs::write(&path, &dot)),
+                None => std::fs::write(&path, &dot),
+            };
+            if let Err(err) = written {
+                let message = format!("Could not write the automaton to '{}': {}!", path.display(), err);
+                return syn::Error::new(span, message).to_compile_error().into();
             }
         }
+    }
 
-        // Add a default failing arm
-        arms.push(quote!{
-            _ => {
-                if let Some((state, kind)) = last_accepting {
-                    // We succeeded before, return that
-                    return (state, kind, last_lex_state.source_index);
-                }
-                else if first_lex_state.is_some() {
-                    // No success before, return an error
-                    return (first_lex_state.unwrap(), Some(#enum_name::#error_token), last_lex_state.source_index);
+    // The functions finding the end of the tokens with a trailing context
+    let mut trailing_context_fns = Vec::new();
+    for (index, (prefix_dfa, context_dfa)) in trailing_contexts.iter().enumerate() {
+        let split = quote::format_ident!("trailing_context_{}", index);
+        let prefix_start = prefix_dfa.start.id();
+        let prefix_step = generate_step(prefix_dfa);
+        let prefix_accepting = generate_accepting(prefix_dfa);
+        let context_start = context_dfa.start.id();
+        let context_step = generate_step(context_dfa);
+        let context_accepting = generate_accepting(context_dfa);
+        trailing_context_fns.push(quote!{
+            fn #split(src: &str, start: &::#FRONT_LIBRARY::LexerState, end: &::#FRONT_LIBRARY::LexerState)
+                -> Option<::#FRONT_LIBRARY::LexerState> {
+
+                fn prefix_step(state: usize, current_char: char) -> Option<usize> { #prefix_step }
+                fn prefix_accepting(state: usize) -> bool { #prefix_accepting }
+                fn context_step(state: usize, current_char: char) -> Option<usize> { #context_step }
+                fn context_accepting(state: usize) -> bool { #context_accepting }
+
+                split_trailing_context(src, start, end,
+                    (#prefix_start, prefix_step, prefix_accepting),
+                    (#context_start, context_step, context_accepting))
+            }
+        });
+    }
+    if !trailing_context_fns.is_empty() {
+        trailing_context_fns.push(quote!{
+            // Finds the longest prefix of the matched text, that is followed
+            // by a matching trailing context. The matchers are the start
+            // state, the transition and the accepting function of a DFA.
+            fn split_trailing_context(src: &str, start: &::#FRONT_LIBRARY::LexerState, end: &::#FRONT_LIBRARY::LexerState,
+                prefix: (usize, fn(usize, char) -> Option<usize>, fn(usize) -> bool),
+                context: (usize, fn(usize, char) -> Option<usize>, fn(usize) -> bool))
+                -> Option<::#FRONT_LIBRARY::LexerState> {
+
+                let text = &src[start.source_index..end.source_index];
+
+                // Collect where the prefix could end, an empty token is not allowed
+                let (mut state, prefix_step, prefix_accepting) = prefix;
+                let mut prefix_ends = Vec::new();
+                for (index, current_char) in text.char_indices() {
+                    match prefix_step(state, current_char) {
+                        Some(next) => state = next,
+                        None => break,
+                    }
+                    if prefix_accepting(state) {
+                        prefix_ends.push(index + current_char.len_utf8());
+                    }
                 }
-                else {
-                    // Nothing consumed, no more characters, it's just the end on input
-                    return (lex_state.clone(), Some(#enum_name::#end_token), last_lex_state.source_index);
+
+                // The rest of the text must match the context exactly
+                let (context_start, context_step, context_accepting) = context;
+                let len = prefix_ends.into_iter().rev().find(|len| {
+                    text[*len..].chars()
+                        .try_fold(context_start, |state, current_char| context_step(state, current_char))
+                        .map_or(false, context_accepting)
+                })?;
+
+                let mut state = start.clone();
+                for current_char in text[..len].chars() {
+                    state.advance(current_char);
                 }
-            },
+                Some(state)
+            }
         });
+    }
 
-        // Add the arms to all the state arms
-        let state_id = state.id();
-        state_transitions.push(quote!{
-            #state_id => match current_char as u32 {
-                #(#arms)*
-            },
-        });
+    // The matching itself
+    let lexing = if lexer_data.table_driven {
+        generate_table_lexer(&dfa, &enum_name, &error_token, &end_token, &FRONT_LIBRARY)
     }
-    // Add a default to the transitions
-    state_transitions.push(quote!{
-        _ => panic!("Unknown state!"),
-    });
+    else {
+        generate_match_lexer(&dfa, &enum_name, &error_token, &end_token, &FRONT_LIBRARY)
+    };
+    // Bytes are matched with tables for both backends, which keeps the code
+    // generated for the UTF-8 automaton small
+    let byte_lexing = generate_byte_table_lexer(&dfa.to_utf8().minimize(), &enum_name, &error_token, &end_token, &FRONT_LIBRARY);
 
     // Wrap it into an internal token parsing function
-    let initial_state_id = dfa.start.id();
     let res = quote!{
         impl ::#FRONT_LIBRARY::TokenType for #enum_name {
             fn is_end(&self) -> bool {
@@ -187,64 +312,22 @@ pub fn yk_lexer(item: TokenStream) -> TokenStream {
             }
 
             fn next_lexeme_internal(src: &str, lex_state: &::#FRONT_LIBRARY::LexerState) -> (::#FRONT_LIBRARY::LexerState, Option<Self>, usize) {
-                let start_idx = lex_state.source_index;
-                let source = &src[start_idx..];
-                let mut source_it = source.char_indices();
-                let mut current_state = #initial_state_id; // State machine state
-
-                let mut last_accepting = None; // Option<(state, Option<token>)>
-                let mut first_lex_state = None; // Option<state>
-                let mut last_lex_state = lex_state.clone();
-
-                loop {
-                    if let Some((current_index, current_char)) = source_it.next() {
-                        let current_char_len = current_char.len_utf8();
-
-                        // Update last lex state's position
-                        match (last_lex_state.last_char, current_char) {
-                            // Newlines
-                              (Some('\r'), '\n')
-                            | (Some('\r'), _)
-                            | (_, '\n') => {
-                                last_lex_state.position.newline();
-                            },
+                #(#trailing_context_fns)*
 
-                            // Any other character
-                            (_, ch) => {
-                                if !ch.is_control() {
-                                    last_lex_state.position.advance_columns(1);
-                                }
-                            }
-                        }
-                        // Update last lex state's index
-                        last_lex_state.source_index += current_char_len;
-                        // Update last lex state's last character
-                        last_lex_state.last_char = Some(current_char);
-
-                        // Save if first
-                        if first_lex_state.is_none() {
-                            first_lex_state = Some(last_lex_state.clone());
-                        }
+                #lexing
+            }
 
-                        match current_state {
-                            #(#state_transitions)*
-                        }
-                    }
-                    else {
-                        if let Some((state, kind)) = last_accepting {
-                            // We succeeded before, return that
-                            return (state, kind, last_lex_state.source_index);
-                        }
-                        else if first_lex_state.is_some() {
-                            // No success before, return an error
-                            return (first_lex_state.unwrap(), Some(#enum_name::#error_token), last_lex_state.source_index);
-                        }
-                        else {
-                            // Nothing consumed, no more characters, it's just the end on input
-                            return (lex_state.clone(), Some(#enum_name::#end_token), last_lex_state.source_index);
-                        }
-                    }
-                }
+            fn next_byte_lexeme_internal(src: &[u8], lex_state: &::#FRONT_LIBRARY::LexerState) -> (::#FRONT_LIBRARY::LexerState, Option<Self>, usize) {
+                #(#trailing_context_fns)*
+
+                #byte_lexing
+            }
+        }
+
+        impl #enum_name {
+            /// The number of states in the automaton of the lexer.
+            pub fn state_counts() -> ::#FRONT_LIBRARY::StateCounts {
+                ::#FRONT_LIBRARY::StateCounts{ determinized: #determinized_states, minimized: #minimized_states }
             }
         }
     };
@@ -252,12 +335,46 @@ pub fn yk_lexer(item: TokenStream) -> TokenStream {
     res.into()
 }
 
-fn parse_attributes(enm: &ItemEnum) -> LexerData {
+fn parse_attributes(enm: &ItemEnum) -> syn::Result<LexerData> {
     // We need to fill these
     let enum_name = enm.ident.clone();
     let mut end_variant = None;
     let mut err_variant = None;
     let mut tokens = Vec::new();
+    let mut fragments = Vec::new();
+    let mut table_driven = false;
+    let mut dot_file = None;
+
+    // Parse the fragment definitions, like #[define(DIGIT = "[0-9]")], and
+    // the backend selection
+    for attr in &enm.attrs {
+        if attr.path.is_ident(ATTRIBUTE_DEFINE) {
+            let defined = attr.parse_args_with(|input: ParseStream| {
+                let mut defined = Vec::new();
+                while !input.is_empty() {
+                    let name: Ident = input.parse()?;
+                    input.parse::<Token![=]>()?;
+                    let regex_lit: LitStr = input.parse()?;
+                    defined.push(Fragment{ name, regex_lit });
+                    if !input.is_empty() {
+                        input.parse::<Token![,]>()?;
+                    }
+                }
+                Ok(defined)
+            })?;
+            fragments.extend(defined);
+        }
+        else if attr.path.is_ident(ATTRIBUTE_TABLE_DRIVEN) {
+            assert!(attr.tokens.is_empty(), "'table_driven' requires no arguments!");
+            table_driven = true;
+        }
+        else if attr.path.is_ident(ATTRIBUTE_DOT_FILE) {
+            match attr.parse_meta()? {
+                syn::Meta::NameValue(syn::MetaNameValue{ lit: syn::Lit::Str(lit), .. }) => dot_file = Some(lit),
+                _ => return Err(syn::Error::new_spanned(attr, "Expected a path, like #[dot_file = \"lexer.dot\"]!")),
+            }
+        }
+    }
 
     // Parse the variants
     for variant in &enm.variants {
@@ -286,11 +403,14 @@ fn parse_attributes(enm: &ItemEnum) -> LexerData {
                 // We should allow things like #[token("if"), token("If")]
                 assert!(current_def.is_none(), "For now only one definition per attribute!");
                 // TODO: Allow '=' too
-                let token = attr.parse_args::<LitStr>().unwrap();
-                let regex_str = regex::escape(&token.value());
+                let DefinitionArgs{ lit, case_insensitive, followed_by } = parse_definition_args(attr)?;
+                let regex_str = regex::escape(&lit.value());
                 current_def = Some(TokenDefinition{
                     variant_ident: variant_ident.clone(),
                     regex_str,
+                    regex_lit: Some(lit),
+                    case_insensitive,
+                    followed_by,
                     precedence: 1,
                     ignore: false,
                 });
@@ -303,6 +423,9 @@ fn parse_attributes(enm: &ItemEnum) -> LexerData {
                 current_def = Some(TokenDefinition{
                     variant_ident: variant_ident.clone(),
                     regex_str: C_IDENT_REGEX.into(),
+                    regex_lit: None,
+                    case_insensitive: false,
+                    followed_by: None,
                     precedence: 0,
                     ignore: false,
                 });
@@ -312,11 +435,14 @@ fn parse_attributes(enm: &ItemEnum) -> LexerData {
                 // We should allow things like #[token("if"), token("If")]
                 assert!(current_def.is_none(), "For now only one definition per attribute!");
                 // TODO: Allow '=' too
-                let token = attr.parse_args::<LitStr>().unwrap();
-                let regex_str = token.value();
+                let DefinitionArgs{ lit, case_insensitive, followed_by } = parse_definition_args(attr)?;
+                let regex_str = lit.value();
                 current_def = Some(TokenDefinition{
                     variant_ident: variant_ident.clone(),
                     regex_str,
+                    regex_lit: Some(lit),
+                    case_insensitive,
+                    followed_by,
                     precedence: 0,
                     ignore: false,
                 });
@@ -336,21 +462,463 @@ fn parse_attributes(enm: &ItemEnum) -> LexerData {
     let err_variant = err_variant.expect("An 'error' variant must be defined!");
     let end_variant = end_variant.expect("An 'end' variant must be defined!");
 
-    LexerData{ enum_name, err_variant, end_variant, tokens, }
+    Ok(LexerData{ enum_name, err_variant, end_variant, tokens, fragments, table_driven, dot_file, })
+}
+
+// Parses the arguments of a token or regex definition, which is the literal
+// and the options after it, like 'case_insensitive' or 'followed_by = "..."'
+fn parse_definition_args(attr: &Attribute) -> syn::Result<DefinitionArgs> {
+    attr.parse_args_with(|input: ParseStream| {
+        let lit: LitStr = input.parse()?;
+        let mut args = DefinitionArgs{ lit, case_insensitive: false, followed_by: None };
+        while !input.is_empty() {
+            input.parse::<Token![,]>()?;
+            let option: Ident = input.parse()?;
+            if option == OPTION_CASE_INSENSITIVE {
+                args.case_insensitive = true;
+            }
+            else if option == OPTION_FOLLOWED_BY {
+                input.parse::<Token![=]>()?;
+                args.followed_by = Some(input.parse()?);
+            }
+            else {
+                return Err(syn::Error::new(option.span(), format!("Unknown option '{}'!", option)));
+            }
+        }
+        Ok(args)
+    })
+}
+
+// Parses and simplifies a regex, the error is reported at the given span
+fn parse_regex(source: &str, flags: regex::Flags, fragments: &HashMap<String, regex::Node>, span: Span)
+    -> Result<regex::Node, syn::Error> {
+
+    // Simplifying first results in a smaller NFA
+    parse_with_fragments(source, flags, |name| fragments.get(name).cloned(), span).map(regex::simplify)
+}
+
+// Parses a regex with the fragment lookup. If it fails on a reference to an
+// undefined fragment, the error names it instead of just the position.
+fn parse_with_fragments<F>(source: &str, flags: regex::Flags, lookup: F, span: Span)
+    -> Result<regex::Node, syn::Error> where F : Fn(&str) -> Option<regex::Node> {
+
+    let undefined = RefCell::new(None);
+    let result = regex::parse_with_fragments(source, flags, |name| {
+        let node = lookup(name);
+        if node.is_none() {
+            undefined.borrow_mut().get_or_insert_with(|| name.to_string());
+        }
+        node
+    });
+    result.map_err(|err| match undefined.into_inner() {
+        Some(name) if err.expected.contains(&"a defined fragment name") =>
+            syn::Error::new(span, format!("Undefined fragment '{}'!", name)),
+        _ => syn::Error::new(span, format!("Error in regex syntax: {}", err)),
+    })
+}
+
+// Returns the fragments resolved with the given flags, resolving them only the
+// first time
+fn fragments_for<'a>(fragments: &[Fragment], flags: regex::Flags,
+    fragment_sets: &'a mut Vec<(regex::Flags, HashMap<String, regex::Node>)>)
+    -> Result<&'a HashMap<String, regex::Node>, syn::Error> {
+
+    let index = match fragment_sets.iter().position(|(f, _)| *f == flags) {
+        Some(index) => index,
+        None => {
+            fragment_sets.push((flags, resolve_fragments(fragments, flags)?));
+            fragment_sets.len() - 1
+        },
+    };
+    Ok(&fragment_sets[index].1)
+}
+
+// Parses the fragments in an order where every fragment comes after the ones it
+// references, reporting undefined and cyclic references
+fn resolve_fragments(fragments: &[Fragment], flags: regex::Flags)
+    -> Result<HashMap<String, regex::Node>, syn::Error> {
+
+    let mut indices = HashMap::new();
+    for (index, fragment) in fragments.iter().enumerate() {
+        if indices.insert(fragment.name.to_string(), index).is_some() {
+            let msg = format!("The fragment '{}' is defined multiple times!", fragment.name);
+            return Err(syn::Error::new(fragment.name.span(), msg));
+        }
+    }
+
+    // First we collect the references, an undefined one is a parse error
+    let mut references = Vec::new();
+    for fragment in fragments {
+        let referenced = RefCell::new(Vec::new());
+        let lookup = |name: &str| indices.get(name).map(|index| {
+            referenced.borrow_mut().push(*index);
+            // Only a placeholder, we are interested in the references
+            regex::Node::Literal(' ')
+        });
+        parse_with_fragments(&fragment.regex_lit.value(), flags, lookup, fragment.regex_lit.span())?;
+        references.push(referenced.into_inner());
+    }
+
+    // Order them with a depth-first search
+    let mut order = Vec::new();
+    let mut visiting = Vec::new();
+    for index in 0..fragments.len() {
+        order_fragments(fragments, &references, index, &mut visiting, &mut order)?;
+    }
+
+    let mut resolved = HashMap::new();
+    for index in order {
+        let fragment = &fragments[index];
+        let node = parse_regex(&fragment.regex_lit.value(), flags, &resolved, fragment.regex_lit.span())?;
+        resolved.insert(fragment.name.to_string(), node);
+    }
+    Ok(resolved)
+}
+
+fn order_fragments(fragments: &[Fragment], references: &[Vec<usize>], index: usize,
+    visiting: &mut Vec<usize>, order: &mut Vec<usize>) -> Result<(), syn::Error> {
+
+    if order.contains(&index) {
+        return Ok(());
+    }
+    if let Some(cycle_start) = visiting.iter().position(|i| *i == index) {
+        // The fragment on the top of the stack closes the cycle
+        let names: Vec<_> = visiting[cycle_start..].iter()
+            .chain(std::iter::once(&index))
+            .map(|i| fragments[*i].name.to_string())
+            .collect();
+        let msg = format!("Cyclic fragment reference {}!", names.join(" -> "));
+        let closing = &fragments[*visiting.last().unwrap()];
+        return Err(syn::Error::new(closing.regex_lit.span(), msg));
+    }
+    visiting.push(index);
+    for referenced in &references[index] {
+        order_fragments(fragments, references, *referenced, visiting, order)?;
+    }
+    visiting.pop();
+    order.push(index);
+    Ok(())
+}
+
+// Generates the lexing with a match arm for each state of the DFA
+fn generate_match_lexer(dfa: &dfa::Automaton<char, AcceptingState>, enum_name: &Ident, error_token: &Ident, end_token: &Ident,
+    front_library: &Ident) -> proc_macro2::TokenStream {
+
+    // We collect each arm of the match
+    let mut state_transitions = Vec::new();
+    for state in dfa.states() {
+        let mut arms = Vec::new();
+
+        // We visit the state's possible transitions
+        if let Some(transitions) = dfa.transitions_from(&state) {
+            // Bring the intervals to closed form, this also gets rid of empty
+            // ranges like ('o'; 'p') and merges adjacent ones
+            let mut transitions = transitions.clone();
+            transitions.normalize();
+
+            for (interval, destination) in &transitions {
+                // We need to generate an arm
+                let lower = to_lower_inclusive_u32(&interval.lower);
+                let upper = to_upper_inclusive_u32(&interval.upper);
+                let arm_pattern = quote!{ #lower..=#upper };
+
+                // Build a "save" statement if the state is an accepting one
+                let acceptor = match dfa.accepting_value(destination) {
+                    Some(accepting) => {
+                        let kind = if accepting.ignore {
+                            quote!{ None }
+                        }
+                        else {
+                            let variant_ident = &accepting.variant_ident;
+                            quote!{ Some(#enum_name::#variant_ident) }
+                        };
+                        match accepting.trailing_context {
+                            Some(index) => {
+                                let split = quote::format_ident!("trailing_context_{}", index);
+                                quote!{
+                                    last_trailing_context = Some((last_lex_state.clone(), #kind, #split))
+                                }
+                            },
+                            None => quote!{
+                                last_accepting = Some((last_lex_state.clone(), #kind));
+                                last_trailing_context = None
+                            },
+                        }
+                    },
+                    None => quote!{},
+                };
+
+                // Build the actual match arm
+                let destination_id = destination.id();
+                let arm = quote!{
+                    #arm_pattern => {
+                        current_state = #destination_id;
+                        #acceptor;
+                    },
+                };
+
+                arms.push(arm);
+            }
+        }
+
+        // Add a default failing arm
+        arms.push(quote!{
+            _ => break,
+        });
+
+        // Add the arms to all the state arms
+        let state_id = state.id();
+        state_transitions.push(quote!{
+            #state_id => match current_char as u32 {
+                #(#arms)*
+            },
+        });
+    }
+    // Add a default to the transitions
+    state_transitions.push(quote!{
+        _ => panic!("Unknown state!"),
+    });
+
+    let initial_state_id = dfa.start.id();
+    quote!{
+        let start_idx = lex_state.source_index;
+        let source = &src[start_idx..];
+        let mut current_state = #initial_state_id; // State machine state
+
+        let mut last_accepting = None; // Option<(state, Option<token>)>
+        // The end of a later match with a trailing context, which is only
+        // split once the match is final
+        let mut last_trailing_context: Option<(::#front_library::LexerState, Option<#enum_name>,
+            fn(&str, &::#front_library::LexerState, &::#front_library::LexerState) -> Option<::#front_library::LexerState>)> = None;
+        let mut first_lex_state = None; // Option<state>
+        let mut last_lex_state = lex_state.clone();
+
+        for current_char in source.chars() {
+            last_lex_state.advance(current_char);
+
+            // Save if first
+            if first_lex_state.is_none() {
+                first_lex_state = Some(last_lex_state.clone());
+            }
+
+            match current_state {
+                #(#state_transitions)*
+            }
+        }
+
+        // The whole match has a split, unless only an empty token could be
+        // split off, then the match before it counts
+        if let Some((end_state, kind, split)) = last_trailing_context {
+            if let Some(state) = split(src, lex_state, &end_state) {
+                return (state, kind, last_lex_state.source_index);
+            }
+        }
+        if let Some((state, kind)) = last_accepting {
+            // We succeeded before, return that
+            (state, kind, last_lex_state.source_index)
+        }
+        else if let Some(state) = first_lex_state {
+            // No success before, return an error
+            (state, Some(#enum_name::#error_token), last_lex_state.source_index)
+        }
+        else {
+            // Nothing consumed, no more characters, it's just the end on input
+            (lex_state.clone(), Some(#enum_name::#end_token), last_lex_state.source_index)
+        }
+    }
+}
+
+// Generates the lexing with static transition tables, that are driven by the
+// generic loop of the front library. The characters are grouped into
+// equivalence classes, and the states with the same transitions share a row.
+fn generate_table_lexer(dfa: &dfa::Automaton<char, AcceptingState>, enum_name: &Ident, error_token: &Ident, end_token: &Ident,
+    front_library: &Ident) -> proc_macro2::TokenStream {
+
+    // A representative character for each class, its transitions stand for
+    // the whole class
+    let classes = dfa.symbol_classes();
+    let mut representatives = Vec::new();
+    for (interval, class) in &classes {
+        if *class == representatives.len() {
+            representatives.push(to_lower_inclusive_u32(&interval.lower));
+        }
+    }
+    let class_count = representatives.len();
+
+    // ASCII characters are looked up directly, the rest by range
+    let ascii_classes = (0..128u8).map(|c| *classes.get(&(c as char)).unwrap() as u32);
+    let mut ranges = Vec::new();
+    for (interval, class) in &classes {
+        let upper = to_upper_inclusive_u32(&interval.upper);
+        if upper < 0x80 {
+            continue;
+        }
+        let lower = char::from_u32(to_lower_inclusive_u32(&interval.lower).max(0x80)).unwrap();
+        let upper = char::from_u32(upper).unwrap();
+        let class = *class as u32;
+        ranges.push(quote!{ (#lower, #upper, #class) });
+    }
+
+    let representatives: Vec<_> = representatives.into_iter().map(|c| char::from_u32(c).unwrap()).collect();
+    let (row_offsets, transitions, accepting) = generate_table_rows(dfa, &representatives, enum_name, front_library);
+
+    let start = dfa.start.id() as u32;
+    quote!{
+        static TABLE: ::#front_library::LexerTable<#enum_name> = ::#front_library::LexerTable{
+            ascii_classes: [#(#ascii_classes),*],
+            classes: &[#(#ranges),*],
+            class_count: #class_count,
+            row_offsets: &[#(#row_offsets),*],
+            transitions: &[#(#transitions),*],
+            accepting: &[#(#accepting),*],
+            start: #start,
+            error: #enum_name::#error_token,
+            end: #enum_name::#end_token,
+        };
+        TABLE.next_lexeme(src, lex_state)
+    }
+}
+
+// Generates the lexing of bytes with static transition tables of the UTF-8
+// automaton, for both backends. The bytes are grouped into classes like the
+// characters of the table backend.
+fn generate_byte_table_lexer(dfa: &dfa::Automaton<u8, AcceptingState>, enum_name: &Ident, error_token: &Ident, end_token: &Ident,
+    front_library: &Ident) -> proc_macro2::TokenStream {
+
+    let classes = dfa.symbol_classes();
+    let byte_classes: Vec<_> = (0..=255u8).map(|b| *classes.get(&b).unwrap()).collect();
+    let mut representatives = Vec::new();
+    for (byte, class) in byte_classes.iter().enumerate() {
+        if *class == representatives.len() {
+            representatives.push(byte as u8);
+        }
+    }
+    let class_count = representatives.len();
+    let byte_classes = byte_classes.into_iter().map(|class| class as u32);
+    let (row_offsets, transitions, accepting) = generate_table_rows(dfa, &representatives, enum_name, front_library);
+
+    let start = dfa.start.id() as u32;
+    quote!{
+        static BYTE_TABLE: ::#front_library::ByteTable<#enum_name> = ::#front_library::ByteTable{
+            classes: [#(#byte_classes),*],
+            class_count: #class_count,
+            row_offsets: &[#(#row_offsets),*],
+            transitions: &[#(#transitions),*],
+            accepting: &[#(#accepting),*],
+            start: #start,
+            error: #enum_name::#error_token,
+            end: #enum_name::#end_token,
+        };
+        BYTE_TABLE.next_lexeme(src, lex_state)
+    }
+}
+
+// Generates the row offsets, the rows and the accepting actions of the states,
+// the identical rows are stored once. The representatives stand for the
+// classes of the symbols.
+fn generate_table_rows<T>(dfa: &dfa::Automaton<T, AcceptingState>, representatives: &[T], enum_name: &Ident,
+    front_library: &Ident) -> (Vec<u32>, Vec<u32>, Vec<proc_macro2::TokenStream>) where T : Ord {
+
+    let mut row_offsets = Vec::new();
+    let mut transitions = Vec::new();
+    let mut rows = HashMap::new();
+    let mut accepting = Vec::new();
+    for state in dfa.states() {
+        let row: Vec<u32> = representatives.iter()
+            .map(|symbol| match dfa.step(state, symbol) {
+                Some(destination) => destination.id() as u32,
+                None => u32::MAX,
+            })
+            .collect();
+        let offset = *rows.entry(row.clone()).or_insert_with(|| {
+            let offset = transitions.len() as u32;
+            transitions.extend(row);
+            offset
+        });
+        row_offsets.push(offset);
+
+        accepting.push(match dfa.accepting_value(&state) {
+            Some(accepting) => {
+                let kind = if accepting.ignore {
+                    quote!{ None }
+                }
+                else {
+                    let variant_ident = &accepting.variant_ident;
+                    quote!{ Some(#enum_name::#variant_ident) }
+                };
+                match accepting.trailing_context {
+                    Some(index) => {
+                        let split = quote::format_ident!("trailing_context_{}", index);
+                        quote!{ ::#front_library::Accepting::TrailingContext(#kind, #split) }
+                    },
+                    None => quote!{ ::#front_library::Accepting::Token(#kind) },
+                }
+            },
+            None => quote!{ ::#front_library::Accepting::No },
+        });
+    }
+    (row_offsets, transitions, accepting)
+}
+
+// Generates the body of a transition function for a DFA, that matches on
+// 'state' and 'current_char'
+fn generate_step(dfa: &dfa::Automaton<char>) -> proc_macro2::TokenStream {
+    let mut state_arms = Vec::new();
+    for state in dfa.states() {
+        let mut arms = Vec::new();
+        if let Some(transitions) = dfa.transitions_from(&state) {
+            let mut transitions = transitions.clone();
+            transitions.normalize();
+            for (interval, destination) in &transitions {
+                let lower = to_lower_inclusive_u32(&interval.lower);
+                let upper = to_upper_inclusive_u32(&interval.upper);
+                let destination_id = destination.id();
+                arms.push(quote!{ #lower..=#upper => Some(#destination_id), });
+            }
+        }
+        let state_id = state.id();
+        state_arms.push(quote!{
+            #state_id => match current_char as u32 {
+                #(#arms)*
+                _ => None,
+            },
+        });
+    }
+    quote!{
+        match state {
+            #(#state_arms)*
+            _ => None,
+        }
+    }
+}
+
+// Generates the body of an accepting-check function for a DFA, that matches on
+// 'state'
+fn generate_accepting(dfa: &dfa::Automaton<char>) -> proc_macro2::TokenStream {
+    let arms = dfa.states()
+        .filter(|state| dfa.is_accepting(state))
+        .map(|state| {
+            let state_id = state.id();
+            quote!{ #state_id => true, }
+        });
+    quote!{
+        match state {
+            #(#arms)*
+            _ => false,
+        }
+    }
 }
 
-fn to_lower_inclusive_u32(b: &LowerBound<char>) -> Option<u32> {
+fn to_lower_inclusive_u32(b: &LowerBound<char>) -> u32 {
     match b {
-        LowerBound::Excluded(c) => Some(*c as u32 + 1),
-        LowerBound::Included(c) => Some(*c as u32),
-        LowerBound::Unbounded => None,
+        LowerBound::Included(c) => *c as u32,
+        _ => unreachable!("Transitions must be normalized!"),
     }
 }
 
-fn to_upper_inclusive_u32(b: &UpperBound<char>) -> Option<u32> {
+fn to_upper_inclusive_u32(b: &UpperBound<char>) -> u32 {
     match b {
-        UpperBound::Excluded(c) => Some(*c as u32 - 1),
-        UpperBound::Included(c) => Some(*c as u32),
-        UpperBound::Unbounded => None,
+        UpperBound::Included(c) => *c as u32,
+        _ => unreachable!("Transitions must be normalized!"),
     }
 }
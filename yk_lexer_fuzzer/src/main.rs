@@ -1,5 +1,6 @@
 
 extern crate yk_lexer;
+extern crate yk_intervals;
 extern crate rand;
 extern crate rand_pcg;
 
@@ -8,6 +9,7 @@ mod str_gen;
 mod fuzz_gen;
 
 use yk_lexer::{StandardLexer, Lexer, TokenType, Token};
+use yk_intervals::IntervalSet;
 use str_gen::*;
 use fuzz_gen::*;
 use std::io::Write;
@@ -43,7 +45,9 @@ enum TokenKind {
     KwElse,
 }
 
-const charset: &str = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ()0123456789 \n";
+fn charset() -> IntervalSet<char> {
+    "{[\n; \n], [ ;  ], [(; )], [0; 9], [A; Z], [a; z]}".parse().unwrap()
+}
 
 fn main() {
     let seed = if true {
@@ -70,7 +74,7 @@ fn create_fuzz_strategy() -> RandomEdit {
 
 fn create_string_strategy() -> RandomStringStrategy {
     let mut sg = RandomStringStrategy::new();
-    sg.add(RandomStringGenerator::with_len_and_charset(0..25, charset));
+    sg.add(RandomClassStringGenerator::with_len_and_class(0..25, charset()));
     {
         let mut tg = RandomTokenGenerator::new();
         tg.add("if");
@@ -7,6 +7,7 @@ use std::ops::Range;
 use std::time::SystemTime;
 use rand::{Rng, SeedableRng};
 use rand_pcg::Mcg128Xsl64;
+use yk_intervals::IntervalSet;
 
 thread_local! {
     static MY_RNG: RefCell<Mcg128Xsl64> = RefCell::new(Mcg128Xsl64::new(0));
@@ -44,11 +45,13 @@ pub fn sample<T>(s: &[T]) -> &T {
     &s[idx]
 }
 
-pub fn rand_string(len: &Range<usize>, charset: &str) -> String {
+pub fn rand_class_string(len: &Range<usize>, class: &IntervalSet<char>) -> String {
     let len = rand_range(len);
+    let card = class.cardinality() as usize;
+    assert!(card > 0 || len == 0, "No characters can be generated from an empty class!");
     let mut res = String::new();
     for _ in 0..len {
-        res.push(*sample(charset.as_bytes()) as char);
+        res.push(class.nth(rand_range(&(0..card)) as u128).expect("The index is below the cardinality!"));
     }
     res
 }
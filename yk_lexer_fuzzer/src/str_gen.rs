@@ -3,6 +3,7 @@
  */
 
 use std::ops::Range;
+use yk_intervals::IntervalSet;
 use crate::rnd::*;
 
 pub trait StringGenStrategy {
@@ -10,23 +11,23 @@ pub trait StringGenStrategy {
 }
 
 /**
- * Random string from a charset.
+ * Random string from a character class.
  */
 
-pub struct RandomStringGenerator {
+pub struct RandomClassStringGenerator {
     len: Range<usize>,
-    charset: String,
+    class: IntervalSet<char>,
 }
 
-impl RandomStringGenerator {
-    pub fn with_len_and_charset(len: Range<usize>, charset: &str) -> Self {
-        Self{ len, charset: charset.into() }
+impl RandomClassStringGenerator {
+    pub fn with_len_and_class(len: Range<usize>, class: IntervalSet<char>) -> Self {
+        Self{ len, class }
     }
 }
 
-impl StringGenStrategy for RandomStringGenerator {
+impl StringGenStrategy for RandomClassStringGenerator {
     fn generate(&self) -> String {
-        rand_string(&self.len, &self.charset)
+        rand_class_string(&self.len, &self.class)
     }
 }
 
@@ -0,0 +1,109 @@
+#!/usr/bin/perl
+# Generates src/unicode_tables.rs from the Unicode database bundled with Perl.
+# Usage: perl scripts/gen_unicode_tables.pl > src/unicode_tables.rs
+
+use strict;
+use warnings;
+use Unicode::UCD qw(prop_invlist prop_values prop_value_aliases all_casefolds);
+
+my @binary = qw(Alphabetic Lowercase Uppercase White_Space XID_Start XID_Continue);
+
+# Converts an inversion list to inclusive ranges, leaving out the surrogates
+sub ranges {
+    my @inv = @_;
+    push @inv, 0x110000 if @inv % 2;
+    my @result;
+    for (my $i = 0; $i < @inv; $i += 2) {
+        my ($from, $to) = ($inv[$i], $inv[$i + 1] - 1);
+        if ($from < 0xD800 && $to >= 0xD800) {
+            push @result, [$from, 0xD7FF];
+            $from = 0xE000;
+        }
+        $from = 0xE000 if $from >= 0xD800 && $from <= 0xDFFF;
+        $to = 0xD7FF if $to >= 0xD800 && $to <= 0xDFFF;
+        push @result, [$from, $to] if $from <= $to;
+    }
+    return @result;
+}
+
+sub loose {
+    my $name = lc shift;
+    $name =~ s/[_\- ]//g;
+    return $name;
+}
+
+my @tables;
+my %names;
+
+sub add_table {
+    my ($ident, $canonical, $invlist, @aliases) = @_;
+    my @r = ranges(@$invlist);
+    return unless @r;
+    push @tables, [$ident, \@r];
+    for my $alias (@aliases) {
+        my $key = loose($alias);
+        die "Ambiguous property name $alias" if exists $names{$key} && $names{$key}[1] ne $ident;
+        $names{$key} = [$canonical, $ident];
+    }
+}
+
+for my $gc (prop_values('gc')) {
+    my @aliases = prop_value_aliases('gc', $gc);
+    add_table('GC_' . uc $aliases[0], $aliases[0], [prop_invlist("gc=$gc")], @aliases);
+}
+for my $sc (sort(prop_values('sc'))) {
+    my @aliases = prop_value_aliases('sc', $sc);
+    next if @aliases < 2 || $aliases[1] eq 'Unknown';
+    add_table('SC_' . uc $aliases[1], $aliases[1], [prop_invlist("sc=$sc")], @aliases);
+}
+for my $prop (@binary) {
+    add_table(uc $prop, $prop, [prop_invlist($prop)], $prop);
+}
+
+print "// Generated by scripts/gen_unicode_tables.pl from the Unicode ";
+print Unicode::UCD::UnicodeVersion(), " character database, do not edit!\n\n";
+print "pub(crate) type Table = &'static [(char, char)];\n\n";
+print "// Property names in loose form (lowercase, without '_', '-' and spaces),\n";
+print "// ordered for binary search, with their canonical names\n";
+print "pub(crate) const PROPERTIES: &[(&str, &str, Table)] = &[\n";
+for my $name (sort keys %names) {
+    my ($canonical, $ident) = @{$names{$name}};
+    print "    (\"$name\", \"$canonical\", $ident),\n";
+}
+print "];\n";
+
+for my $table (@tables) {
+    my ($ident, $ranges) = @$table;
+    print "\nconst $ident: Table = &[\n";
+    my @items = map { sprintf("('\\u{%X}', '\\u{%X}')", @$_) } @$ranges;
+    while (my @line = splice(@items, 0, 4)) {
+        print "    ", join(", ", @line), ",\n";
+    }
+    print "];\n";
+}
+
+# Simple case folding, grouped into classes of characters folding together
+my %classes;
+my $folds = all_casefolds();
+for my $entry (values %$folds) {
+    next if $entry->{simple} eq '';
+    my ($code, $fold) = (hex $entry->{code}, hex $entry->{simple});
+    $classes{$fold}{$fold} = 1;
+    $classes{$fold}{$code} = 1;
+}
+my %equivalents;
+for my $class (values %classes) {
+    my @members = sort { $a <=> $b } keys %$class;
+    for my $member (@members) {
+        $equivalents{$member} = [grep { $_ != $member } @members];
+    }
+}
+
+print "\n// Every character that has case-insensitive equivalents under simple case\n";
+print "// folding, ordered for binary search, with the other members of its class\n";
+print "pub(crate) const CASE_CLASSES: &[(char, &[char])] = &[\n";
+for my $code (sort { $a <=> $b } keys %equivalents) {
+    my @others = map { sprintf("'\\u{%X}'", $_) } @{$equivalents{$code}};
+    printf("    ('\\u{%X}', &[%s]),\n", $code, join(", ", @others));
+}
+print "];\n";
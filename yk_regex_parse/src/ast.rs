@@ -2,8 +2,6 @@
  * All of the regex syntax-tree (AST) data-structures.
  */
 
-// TODO: Character classes, like [[:alpha:]], ...
-
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Node {
     Alternative{
@@ -11,11 +9,22 @@ pub enum Node {
         second: Box<Node>,
     },
 
+    /// Matches what both of the subnodes match
+    Intersection{
+        first: Box<Node>,
+        second: Box<Node>,
+    },
+
     Sequence{
         first: Box<Node>,
         second: Box<Node>,
     },
 
+    /// Matches exactly what the subnode doesn't match
+    Complement{
+        subnode: Box<Node>,
+    },
+
     Quantified{
         subnode: Box<Node>,
         quantifier: Quantifier,
@@ -39,4 +48,27 @@ pub enum Quantifier {
 pub enum GroupingElement {
     Literal(char),
     Range(char, char),
+    Property{
+        negated: bool,
+        property: Property,
+    },
+}
+
+/// A Unicode property with a bundled table, like the L in \p{L}
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Property {
+    pub(crate) name: &'static str,
+    pub(crate) ranges: &'static [(char, char)],
+}
+
+impl Property {
+    /// The canonical name of the property.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// The ordered, disjunct, inclusive character ranges having the property.
+    pub fn ranges(&self) -> &'static [(char, char)] {
+        self.ranges
+    }
 }
@@ -0,0 +1,89 @@
+/**
+ * Case-insensitivity, by expanding literals and groupings to every character
+ * that is equivalent under Unicode simple case folding. This way the automata
+ * don't have to know anything about case.
+ */
+
+use crate::ast::{Node, GroupingElement};
+use crate::unicode_tables::CASE_CLASSES;
+
+/// The characters that are case-insensitively equal to the given one, not
+/// including the character itself.
+pub(crate) fn equivalents(c: char) -> &'static [char] {
+    match CASE_CLASSES.binary_search_by(|(k, _)| k.cmp(&c)) {
+        Ok(idx) => CASE_CLASSES[idx].1,
+        Err(_) => &[],
+    }
+}
+
+/// Makes a literal match case-insensitively. Characters without case stay
+/// literals, the others become a grouping of their equivalents.
+pub(crate) fn fold_literal(c: char) -> Node {
+    let others = equivalents(c);
+    if others.is_empty() {
+        Node::Literal(c)
+    }
+    else {
+        let elements = std::iter::once(c).chain(others.iter().cloned()).map(GroupingElement::Literal).collect();
+        Node::Grouping{ negated: false, elements }
+    }
+}
+
+/// Extends the grouping elements with the equivalents of every character they
+/// contain. Properties are kept as-is.
+pub(crate) fn fold_elements(elements: Vec<GroupingElement>) -> Vec<GroupingElement> {
+    let mut result = elements.clone();
+    for element in elements {
+        let (from, to) = match element {
+            GroupingElement::Literal(c) => (c, c),
+            GroupingElement::Range(a, b) => (a, b),
+            GroupingElement::Property{ .. } => continue,
+        };
+        // Only the characters with equivalents are interesting from the range
+        let start = CASE_CLASSES.partition_point(|(k, _)| *k < from);
+        for (_, others) in CASE_CLASSES[start..].iter().take_while(|(k, _)| *k <= to) {
+            result.extend(others.iter().cloned().map(GroupingElement::Literal));
+        }
+    }
+    result
+}
+
+// Tests ///////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod case_tests {
+    use super::*;
+    use GroupingElement::{Literal, Range};
+
+    #[test]
+    fn ascii_equivalents() {
+        assert_eq!(equivalents('a'), &['A']);
+        assert_eq!(equivalents('Q'), &['q']);
+        assert!(equivalents('1').is_empty());
+    }
+
+    #[test]
+    fn non_ascii_equivalents() {
+        assert_eq!(equivalents('k'), &['K', '\u{212A}']);
+        assert_eq!(equivalents('ő'), &['Ő']);
+        assert_eq!(equivalents('σ'), &['Σ', 'ς']);
+    }
+
+    #[test]
+    fn fold_caseless_literal() {
+        assert_eq!(fold_literal('_'), Node::Literal('_'));
+    }
+
+    #[test]
+    fn fold_cased_literal() {
+        assert_eq!(fold_literal('s'), Node::Grouping{ negated: false, elements: vec![Literal('s'), Literal('S'), Literal('ſ')] });
+    }
+
+    #[test]
+    fn fold_range() {
+        assert_eq!(
+            fold_elements(vec![Range('x', 'z'), Literal('1')]),
+            vec![Range('x', 'z'), Literal('1'), Literal('X'), Literal('Y'), Literal('Z')]
+        );
+    }
+}
@@ -0,0 +1,142 @@
+/**
+ * Predefined character classes, like [[:alpha:]] and \d. They are all ASCII
+ * and lowered to grouping elements right in the parser. Unicode properties,
+ * like \p{L} are looked up in the bundled tables.
+ */
+
+use crate::ast::{GroupingElement::{self, Literal, Range}, Property};
+use crate::unicode_tables::PROPERTIES;
+
+const ALNUM: &[GroupingElement] = &[Range('0', '9'), Range('A', 'Z'), Range('a', 'z')];
+const ALPHA: &[GroupingElement] = &[Range('A', 'Z'), Range('a', 'z')];
+const BLANK: &[GroupingElement] = &[Literal('\t'), Literal(' ')];
+const CNTRL: &[GroupingElement] = &[Range('\0', '\x1F'), Literal('\x7F')];
+const DIGIT: &[GroupingElement] = &[Range('0', '9')];
+const GRAPH: &[GroupingElement] = &[Range('!', '~')];
+const LOWER: &[GroupingElement] = &[Range('a', 'z')];
+const PRINT: &[GroupingElement] = &[Range(' ', '~')];
+const PUNCT: &[GroupingElement] = &[Range('!', '/'), Range(':', '@'), Range('[', '`'), Range('{', '~')];
+const SPACE: &[GroupingElement] = &[Range('\t', '\r'), Literal(' ')];
+const UPPER: &[GroupingElement] = &[Range('A', 'Z')];
+const WORD: &[GroupingElement] = &[Range('0', '9'), Range('A', 'Z'), Literal('_'), Range('a', 'z')];
+const XDIGIT: &[GroupingElement] = &[Range('0', '9'), Range('A', 'F'), Range('a', 'f')];
+
+/// The elements of a POSIX class by name, as in [[:name:]].
+pub(crate) fn posix_class(name: &str) -> Option<&'static [GroupingElement]> {
+    match name {
+        "alnum" => Some(ALNUM),
+        "alpha" => Some(ALPHA),
+        "blank" => Some(BLANK),
+        "cntrl" => Some(CNTRL),
+        "digit" => Some(DIGIT),
+        "graph" => Some(GRAPH),
+        "lower" => Some(LOWER),
+        "print" => Some(PRINT),
+        "punct" => Some(PUNCT),
+        "space" => Some(SPACE),
+        "upper" => Some(UPPER),
+        "word" => Some(WORD),
+        "xdigit" => Some(XDIGIT),
+        _ => None,
+    }
+}
+
+/// The elements of a shorthand class, as in \d, and whether it's negated.
+pub(crate) fn shorthand_class(c: char) -> Option<(bool, &'static [GroupingElement])> {
+    match c {
+        'd' => Some((false, DIGIT)),
+        'w' => Some((false, WORD)),
+        's' => Some((false, SPACE)),
+        'D' => Some((true, DIGIT)),
+        'W' => Some((true, WORD)),
+        'S' => Some((true, SPACE)),
+        _ => None,
+    }
+}
+
+/// Looks up a Unicode property by name, like "L", "Greek", "XID_Start" or
+/// "Script=Greek". Names are matched loosely, ignoring case, spaces, '-' and
+/// '_'.
+pub(crate) fn unicode_property(name: &str) -> Option<Property> {
+    let value = match name.find('=') {
+        Some(idx) => {
+            match loose(&name[..idx]).as_str() {
+                "gc" | "generalcategory" | "sc" | "script" => &name[(idx + 1)..],
+                _ => return None,
+            }
+        },
+        None => name,
+    };
+    let value = loose(value);
+    PROPERTIES.binary_search_by(|(n, _, _)| (*n).cmp(value.as_str()))
+        .ok()
+        .map(|idx| Property{ name: PROPERTIES[idx].1, ranges: PROPERTIES[idx].2 })
+}
+
+fn loose(name: &str) -> String {
+    name.chars().filter(|c| !"_- ".contains(*c)).map(|c| c.to_ascii_lowercase()).collect()
+}
+
+/// The elements matching every character not in the given class. The class
+/// elements must be ordered and disjunct.
+pub(crate) fn complement(elements: &[GroupingElement]) -> Vec<GroupingElement> {
+    let mut result = Vec::new();
+    let mut next = Some('\0');
+    for element in elements {
+        let (from, to) = match *element {
+            Literal(c) => (c, c),
+            Range(a, b) => (a, b),
+            GroupingElement::Property{ .. } => unreachable!("The predefined classes have no properties!"),
+        };
+        if let Some(n) = next {
+            if n < from {
+                result.push(Range(n, std::char::from_u32(from as u32 - 1).unwrap()));
+            }
+        }
+        next = std::char::from_u32(to as u32 + 1);
+    }
+    if let Some(n) = next {
+        result.push(Range(n, std::char::MAX));
+    }
+    result
+}
+
+// Tests ///////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod class_tests {
+    use super::*;
+
+    #[test]
+    fn property_lookup() {
+        assert_eq!(unicode_property("L").map(|p| p.name()), Some("L"));
+        assert_eq!(unicode_property("letter").map(|p| p.name()), Some("L"));
+        assert_eq!(unicode_property("Script=Grek").map(|p| p.name()), Some("Greek"));
+        assert_eq!(unicode_property("xid-start").map(|p| p.name()), Some("XID_Start"));
+        assert_eq!(unicode_property("Klingon"), None);
+        assert_eq!(unicode_property("foo=L"), None);
+    }
+
+    #[test]
+    fn property_contents() {
+        let contains = |name: &str, c: char| unicode_property(name).unwrap().ranges().iter().any(|(a, b)| *a <= c && c <= *b);
+        assert!(contains("Lu", 'Á'));
+        assert!(!contains("Lu", 'á'));
+        assert!(contains("Nd", '٣'));
+        assert!(contains("Greek", 'λ'));
+        assert!(!contains("Greek", 'l'));
+        assert!(contains("XID_Start", 'ő'));
+        assert!(!contains("XID_Start", '1'));
+        assert!(contains("XID_Continue", '1'));
+    }
+
+    #[test]
+    fn complement_of_digits() {
+        assert_eq!(complement(DIGIT), vec![Range('\0', '/'), Range(':', std::char::MAX)]);
+    }
+
+    #[test]
+    fn complement_touching_start() {
+        assert_eq!(complement(CNTRL), vec![Range(' ', '~'), Range('\u{80}', std::char::MAX)]);
+    }
+}
@@ -1,6 +1,11 @@
-
 mod ast;
+mod case;
+mod class;
 mod parser;
+mod printer;
+mod simplify;
+mod unicode_tables;
 
-pub use ast::{Node, Quantifier, GroupingElement};
-pub use parser::{parse, escape};
+pub use ast::{Node, Quantifier, GroupingElement, Property};
+pub use parser::{parse, parse_with_flags, parse_with_fragments, escape, Flags, ParseError, MAX_REPETITION};
+pub use simplify::simplify;
@@ -2,18 +2,37 @@
  * A simple parser to parse a string into a regex AST.
  */
 
-use crate::ast::{Node, Quantifier, GroupingElement};
+use std::cell::RefCell;
+use std::fmt;
+use crate::ast::{Node, Quantifier, GroupingElement, Property};
+use crate::case;
+use crate::class;
 
 /*
  * Reference grammar for the parser:
  *
  * alternative ::=
- *               | sequence '|' alternative
+ *               | intersection '|' alternative
+ *               | intersection
+ *               ;
+ *
+ * intersection ::=
+ *               | sequence '&' intersection
  *               | sequence
  *               ;
  *
  * sequence    ::=
- *               | quantified sequence
+ *               | flags? complement sequence
+ *               | flags? complement
+ *               ;
+ *
+ * flags       ::=
+ *               | '(' '?' FLAG* ')'
+ *               | '(' '?' FLAG* '-' FLAG* ')'
+ *               ;
+ *
+ * complement  ::=
+ *               | '~' complement
  *               | quantified
  *               ;
  *
@@ -24,13 +43,29 @@ use crate::ast::{Node, Quantifier, GroupingElement};
  *
  * quantifier  ::=
  *               | '?' | '*' | '+'
+ *               | '{' NUMBER '}'
+ *               | '{' NUMBER ',' '}'
+ *               | '{' NUMBER ',' NUMBER '}'
  *               ;
  *
  * atom        ::=
  *               | '(' alternative ')'
  *               | '[' group ']'
+ *               | '[' '^' group ']'
+ *               | '.'
+ *               | '{' FRAGMENT_NAME '}'
  *               | ANY_NONSPECIAL_CHAR
+ *               | escape
+ *               | '\' CLASS_CHAR
+ *               | property
+ *               ;
+ *
+ * escape      ::=
  *               | '\' ANY_SPECIAL_CHAR
+ *               | '\' ('-' | '^')
+ *               | '\' ('n' | 't' | 'r' | '0')
+ *               | '\' 'x' HEX_DIGIT HEX_DIGIT
+ *               | '\' 'u' '{' HEX_DIGIT+ '}'
  *               ;
  *
  * group       ::=
@@ -39,38 +74,167 @@ use crate::ast::{Node, Quantifier, GroupingElement};
  *               ;
  *
  * group_init  ::=
+ *               | class
  *               | group_iatom '-' group_atom
  *               | group_iatom
  *               ;
  *
  * group_rem   ::=
+ *               | class
  *               | group_atom '-' group_atom
  *               | group_atom
  *               ;
  *
+ * class       ::=
+ *               | '[' ':' CLASS_NAME ':' ']'
+ *               | '\' CLASS_CHAR
+ *               | property
+ *               ;
+ *
+ * property    ::=
+ *               | '\' ('p' | 'P') '{' PROPERTY_NAME '}'
+ *               | '\' ('p' | 'P') PROPERTY_CHAR
+ *               ;
+ *
  * group_iatom ::=
  *               | ']'
  *               | group_atom
  *               ;
  *
  * group_atom  ::=
- *               | ANY_NONSPECIAL_CHAR
- *               | '\' ANY_SPECIAL_CHAR
+ *               | ANY_NONSPECIAL_GROUPING_CHAR
+ *               | escape
  *               ;
  */
 
+/**
+ * Errors.
+ */
+
+/// Describes why a regex could not be parsed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// The byte offset in the source where parsing failed
+    pub position: usize,
+    /// The character at the position, None if the source ended
+    pub found: Option<char>,
+    /// The constructs that could have been accepted at the position
+    pub expected: Vec<&'static str>,
+}
+
+impl ParseError {
+    // Keeps the error that got further, as that's the one the user most likely
+    // wants to see. For the same position the expectations are unified.
+    fn merge(mut self, other: ParseError) -> ParseError {
+        if other.position > self.position {
+            other
+        }
+        else if other.position < self.position {
+            self
+        }
+        else {
+            for e in other.expected {
+                if !self.expected.contains(&e) {
+                    self.expected.push(e);
+                }
+            }
+            self
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.found {
+            Some(c) => write!(f, "Unexpected {:?} at byte {}", c, self.position)?,
+            None => write!(f, "Unexpected end of regex at byte {}", self.position)?,
+        }
+        match self.expected.len() {
+            0 => {},
+            1 => write!(f, ", expected {}", self.expected[0])?,
+            _ => write!(f, ", expected one of {}", self.expected.join(", "))?,
+        }
+        write!(f, "!")
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+// Names of the expected constructs
+const EXPECT_ATOM: &[&str] = &["'~'", "'('", "'['", "'.'", "a fragment reference", "a non-special character", "an escape sequence"];
+const EXPECT_ESCAPABLE: &[&str] = &["an escapable character"];
+const EXPECT_GROUPING_ATOM: &[&str] = &["a non-special character", "an escape sequence"];
+const EXPECT_CLASS: &[&str] = &["a character class"];
+const EXPECT_PROPERTY: &[&str] = &["a Unicode property name"];
+const EXPECT_QUANTIFIER: &[&str] = &["a quantifier"];
+const EXPECT_END: &[&str] = &["'|'", "'&'", "the end of the regex"];
+const EXPECT_HEX_DIGIT: &[&str] = &["a hexadecimal digit"];
+const EXPECT_FLAG: &[&str] = &["a flag", "')'"];
+const EXPECT_FRAGMENT: &[&str] = &["a defined fragment name"];
+// Must agree with MAX_REPETITION
+const EXPECT_REPETITION_COUNT: &[&str] = &["a repetition count of at most 1000"];
+
+/// The largest count allowed in a counted repetition. The automata copy the
+/// repeated part for each count, so larger counts could exhaust the memory.
+pub const MAX_REPETITION: usize = 1000;
+
+/// Flags that alter the meaning of the regex constructs. They can also be
+/// changed inside the regex with (?is-is), until the end of the enclosing
+/// group.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Flags {
+    /// When set, '.' matches newlines too, the inline flag is 's'
+    pub dot_matches_newline: bool,
+    /// When set, letters match regardless of their case, using Unicode simple
+    /// case folding, the inline flag is 'i'
+    pub case_insensitive: bool,
+}
+
 /// A small helper to ease the Chars interface a bit
 #[derive(Clone)]
-struct Chars<'a>(std::str::Chars<'a>);
+struct Chars<'a> {
+    source: &'a str,
+    it: std::str::Chars<'a>,
+    // Backtracking throws away errors, so the furthest one is recorded here
+    furthest: &'a RefCell<Option<ParseError>>,
+    flags: Flags,
+    // Looks up the named fragments referenced as {NAME}
+    fragments: &'a dyn Fn(&str) -> Option<Node>,
+}
 
 impl <'a> Chars<'a> {
     fn next(&self) -> Option<(char, Chars<'a>)> {
         let mut clone = self.clone();
-        match clone.0.next() {
+        match clone.it.next() {
             Some(c) => Some((c, clone)),
             None => None,
         }
     }
+
+    fn position(&self) -> usize {
+        self.source.len() - self.it.as_str().len()
+    }
+
+    fn skip(&self, bytes: usize) -> Chars<'a> {
+        let mut clone = self.clone();
+        clone.it = self.it.as_str()[bytes..].chars();
+        clone
+    }
+
+    /// Creates an error at the current position and records it.
+    fn error(&self, expected: &[&'static str]) -> ParseError {
+        let err = ParseError{
+            position: self.position(),
+            found: self.it.clone().next(),
+            expected: expected.to_vec(),
+        };
+        let mut furthest = self.furthest.borrow_mut();
+        *furthest = Some(match furthest.take() {
+            Some(f) => f.merge(err.clone()),
+            None => err.clone(),
+        });
+        err
+    }
 }
 
 /**
@@ -96,20 +260,32 @@ pub fn escape(source: &str) -> String {
  * Actual parsing.
  */
 
-type ParseResult<'a, T> = Result<(T, Chars<'a>), &'static str>;
+type ParseResult<'a, T> = Result<(T, Chars<'a>), ParseError>;
 
-pub fn parse(source: &str) -> Result<Node, &'static str> {
-    match parse_alternative(Chars(source.chars())) {
-        Ok((n, it)) => {
-            if let Some(_) = it.next() {
-                Err("Unexpected character!")
-            }
-            else {
-                Ok(*n)
-            }
-        },
-        Err(v) => Err(v),
+pub fn parse(source: &str) -> Result<Node, ParseError> {
+    parse_with_flags(source, Flags::default())
+}
+
+pub fn parse_with_flags(source: &str, flags: Flags) -> Result<Node, ParseError> {
+    parse_with_fragments(source, flags, |_| None)
+}
+
+/// Parses the regex, resolving the fragment references, like {DIGIT}, with
+/// the given lookup. The fragments are substituted as they are returned, so
+/// the flags of the referencing regex don't apply to them.
+pub fn parse_with_fragments<F>(source: &str, flags: Flags, fragments: F) -> Result<Node, ParseError>
+    where F : Fn(&str) -> Option<Node> {
+
+    let furthest = RefCell::new(None);
+    let it = Chars{ source, it: source.chars(), furthest: &furthest, flags, fragments: &fragments };
+    if let Ok((n, it)) = parse_alternative(it) {
+        if it.next().is_none() {
+            return Ok(*n);
+        }
+        it.error(EXPECT_END);
     }
+    // Report the error that got the furthest
+    Err(furthest.into_inner().unwrap())
 }
 
 // TODO: There could be a lit cleaned up VIA pattern guards, but the stabilization
@@ -117,7 +293,7 @@ pub fn parse(source: &str) -> Result<Node, &'static str> {
 // https://github.com/rust-lang/rust/pull/63118
 
 fn parse_alternative(it: Chars<'_>) -> ParseResult<'_, Box<Node>> {
-    let (first, it) = parse_sequence(it)?;
+    let (first, it) = parse_intersection(it)?;
     if let Some(('|', it)) = it.next() {
         let (second, it) = parse_alternative(it)?;
         Ok((Box::new(Node::Alternative{ first, second }), it))
@@ -127,8 +303,27 @@ fn parse_alternative(it: Chars<'_>) -> ParseResult<'_, Box<Node>> {
     }
 }
 
+fn parse_intersection(it: Chars<'_>) -> ParseResult<'_, Box<Node>> {
+    let (first, it) = parse_sequence(it)?;
+    if let Some(('&', it)) = it.next() {
+        let (second, it) = parse_intersection(it)?;
+        Ok((Box::new(Node::Intersection{ first, second }), it))
+    }
+    else {
+        Ok((first, it))
+    }
+}
+
 fn parse_sequence(it: Chars<'_>) -> ParseResult<'_, Box<Node>> {
-    let (first, it) = parse_quantified(it)?;
+    let it = if it.it.as_str().starts_with("(?") {
+        let (flags, mut it) = parse_flags(it.skip(2))?;
+        it.flags = flags;
+        it
+    }
+    else {
+        it
+    };
+    let (first, it) = parse_complement(it)?;
     if let Ok((second, it)) = parse_sequence(it.clone()) {
         Ok((Box::new(Node::Sequence{ first, second }), it))
     }
@@ -137,6 +332,40 @@ fn parse_sequence(it: Chars<'_>) -> ParseResult<'_, Box<Node>> {
     }
 }
 
+// Parses the flags of an inline flag group, right after the '(?'
+fn parse_flags(mut it: Chars<'_>) -> ParseResult<'_, Flags> {
+    let mut flags = it.flags;
+    let mut enable = true;
+    loop {
+        match it.next() {
+            Some(('i', next)) => {
+                flags.case_insensitive = enable;
+                it = next;
+            },
+            Some(('s', next)) => {
+                flags.dot_matches_newline = enable;
+                it = next;
+            },
+            Some(('-', next)) if enable => {
+                enable = false;
+                it = next;
+            },
+            Some((')', next)) => return Ok((flags, next)),
+            _ => return Err(it.error(EXPECT_FLAG)),
+        }
+    }
+}
+
+fn parse_complement(it: Chars<'_>) -> ParseResult<'_, Box<Node>> {
+    if let Some(('~', it)) = it.next() {
+        let (subnode, it) = parse_complement(it)?;
+        Ok((Box::new(Node::Complement{ subnode }), it))
+    }
+    else {
+        parse_quantified(it)
+    }
+}
+
 fn parse_quantified(it: Chars<'_>) -> ParseResult<'_, Box<Node>> {
     let (subnode, it) = parse_atom(it)?;
     if let Ok((quantifier, it)) = parse_quantifier(it.clone()) {
@@ -147,26 +376,62 @@ fn parse_quantified(it: Chars<'_>) -> ParseResult<'_, Box<Node>> {
     }
 }
 
-// TODO: Implement X{N} (At least N) and {N, M} (Between N and M)
 fn parse_quantifier(it: Chars<'_>) -> ParseResult<'_, Quantifier> {
     match it.next() {
         Some(('?', it)) => Ok((Quantifier::Between(0, 1), it)),
         Some(('+', it)) => Ok((Quantifier::AtLeast(1), it)),
         Some(('*', it)) => Ok((Quantifier::AtLeast(0), it)),
+        Some(('{', it)) => parse_counted_quantifier(it),
 
-        _ => Err("Unknown quantifier!"),
+        _ => Err(it.error(EXPECT_QUANTIFIER)),
+    }
+}
+
+fn parse_counted_quantifier(it: Chars<'_>) -> ParseResult<'_, Quantifier> {
+    let (least, it) = parse_number(it)?;
+    let (quantifier, it) = match it.next() {
+        Some((',', after_comma)) => {
+            if let Some(('}', _)) = after_comma.next() {
+                (Quantifier::AtLeast(least), after_comma)
+            }
+            else {
+                let (most, it) = parse_number(after_comma.clone())?;
+                if most < least {
+                    return Err(after_comma.error(&["an upper bound not less than the lower bound"]));
+                }
+                (Quantifier::Between(least, most), it)
+            }
+        },
+        _ => (Quantifier::Between(least, least), it),
+    };
+    match it.next() {
+        Some(('}', it)) => Ok((quantifier, it)),
+        _ => Err(it.error(&["'}'"])),
+    }
+}
+
+fn parse_number(it: Chars<'_>) -> ParseResult<'_, usize> {
+    let digits = it.it.as_str().find(|c: char| !c.is_ascii_digit()).unwrap_or(it.it.as_str().len());
+    if digits == 0 {
+        return Err(it.error(&["a number"]));
+    }
+    match it.it.as_str()[..digits].parse() {
+        Ok(n) if n <= MAX_REPETITION => Ok((n, it.skip(digits))),
+        _ => Err(it.error(EXPECT_REPETITION_COUNT)),
     }
 }
 
 fn parse_atom(it: Chars<'_>) -> ParseResult<'_, Box<Node>> {
     match it.next() {
-        Some(('(', it)) => {
-            let (node, it) = parse_alternative(it)?;
-            if let Some((')', it)) = it.next() {
-                Ok((node, it))
+        Some(('(', inner)) => {
+            let (node, inner) = parse_alternative(inner)?;
+            if let Some((')', mut next)) = inner.next() {
+                // Inline flags don't leak out of the group
+                next.flags = it.flags;
+                Ok((node, next))
             }
             else {
-                Err("Expected matching ')'!")
+                Err(inner.error(&["'|'", "'&'", "')'"]))
             }
         },
 
@@ -176,66 +441,174 @@ fn parse_atom(it: Chars<'_>) -> ParseResult<'_, Box<Node>> {
                 Ok((node, it))
             }
             else {
-                Err("Expected matching ']'!")
+                Err(it.error(&["']'"]))
             }
         },
 
-        Some(('\\', it)) => {
-            if let Some((ch, it)) = it.next() {
-                if let Some(ch) = to_escaped(ch) {
-                    Ok((Box::new(Node::Literal(ch)), it))
-                }
-                else {
-                    Err("Cannot escape character!")
-                }
+        Some(('{', next)) => {
+            let name_len = next.it.as_str()
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(next.it.as_str().len());
+            let name = &next.it.as_str()[..name_len];
+            if !name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+                return Err(next.error(&["a fragment name"]));
+            }
+            let node = (next.fragments)(name).ok_or_else(|| next.error(EXPECT_FRAGMENT))?;
+            let rest = next.skip(name_len);
+            match rest.next() {
+                Some(('}', it)) => Ok((Box::new(node), it)),
+                _ => Err(rest.error(&["'}'"])),
+            }
+        },
+
+        Some(('.', it)) => {
+            // Any character, except for a newline if not asked otherwise
+            let node = if it.flags.dot_matches_newline {
+                Node::Grouping{ negated: false, elements: vec![GroupingElement::Range('\0', std::char::MAX)] }
+            }
+            else {
+                Node::Grouping{ negated: true, elements: vec![GroupingElement::Literal('\n')] }
+            };
+            Ok((Box::new(node), it))
+        },
+
+        Some(('\\', next)) => {
+            if let Some((ch @ ('p' | 'P'), it)) = next.next() {
+                let (property, it) = parse_property_name(it)?;
+                let elements = vec![GroupingElement::Property{ negated: false, property }];
+                Ok((Box::new(Node::Grouping{ negated: ch == 'P', elements }), it))
+            }
+            else if let Some((Some((negated, elements)), it)) = next.next().map(|(c, it)| (class::shorthand_class(c), it)) {
+                Ok((Box::new(Node::Grouping{ negated, elements: elements.to_vec() }), it))
             }
             else {
-                Err("Expected a character after '\'!")
+                let (ch, it) = parse_escape(next)?;
+                Ok((literal(ch, it.flags), it))
             }
         },
 
-        Some((c, it)) => {
+        Some((c, next)) => {
             if is_nonspecial_char(c) {
-                Ok((Box::new(Node::Literal(c)), it))
+                Ok((literal(c, next.flags), next))
             }
             else {
-                Err("Expected a non-special character! (Did you forget to escape it?)")
+                Err(it.error(EXPECT_ATOM))
             }
         },
 
-        None => Err("Expected a character!"),
+        None => Err(it.error(EXPECT_ATOM)),
     }
 }
 
 fn parse_grouping(it: Chars<'_>) -> ParseResult<'_, Box<Node>> {
-    // TODO: Negated value
-    let (first, mut it) = parse_grouping_element_init(it)?;
-    let mut elements = vec![first];
+    let (negated, it) = match it.next() {
+        Some(('^', it)) => (true, it),
+        _ => (false, it),
+    };
+    let (mut elements, mut it) = parse_grouping_element_init(it)?;
     while let Ok((nth, nextit)) = parse_grouping_element(it.clone()) {
         it = nextit;
-        elements.push(nth);
+        elements.extend(nth);
     }
-    Ok((Box::new(Node::Grouping{ negated: false, elements }), it))
+    let elements = if it.flags.case_insensitive { case::fold_elements(elements) } else { elements };
+    Ok((Box::new(Node::Grouping{ negated, elements }), it))
 }
 
-fn parse_grouping_element_init(it: Chars<'_>) -> ParseResult<'_, GroupingElement> {
-    let (left, it) = parse_grouping_atom_init(it)?;
-    if let Some(('-', it)) = it.next() {
-        if let Ok((right, it)) = parse_grouping_atom(it) {
-            return Ok((GroupingElement::Range(left, right), it));
-        }
+fn literal(c: char, flags: Flags) -> Box<Node> {
+    if flags.case_insensitive {
+        Box::new(case::fold_literal(c))
+    }
+    else {
+        Box::new(Node::Literal(c))
+    }
+}
+
+fn parse_grouping_element_init(it: Chars<'_>) -> ParseResult<'_, Vec<GroupingElement>> {
+    if let Ok(result) = parse_class(it.clone()) {
+        return Ok(result);
     }
-    return Ok((GroupingElement::Literal(left), it));
+    let (left, it) = parse_grouping_atom_init(it)?;
+    Ok(parse_grouping_range(left, it))
 }
 
-fn parse_grouping_element(it: Chars<'_>) -> ParseResult<'_, GroupingElement> {
+fn parse_grouping_element(it: Chars<'_>) -> ParseResult<'_, Vec<GroupingElement>> {
+    if let Ok(result) = parse_class(it.clone()) {
+        return Ok(result);
+    }
     let (left, it) = parse_grouping_atom(it)?;
-    if let Some(('-', it)) = it.next() {
-        if let Ok((right, it)) = parse_grouping_atom(it) {
-            return Ok((GroupingElement::Range(left, right), it));
+    Ok(parse_grouping_range(left, it))
+}
+
+// Finishes a grouping element that started with a character, it's either a
+// range or the single character
+fn parse_grouping_range(left: char, it: Chars<'_>) -> (Vec<GroupingElement>, Chars<'_>) {
+    if let Some(('-', next)) = it.next() {
+        if let Ok((right, next)) = parse_grouping_atom(next) {
+            return (vec![GroupingElement::Range(left, right)], next);
         }
     }
-    return Ok((GroupingElement::Literal(left), it));
+    (vec![GroupingElement::Literal(left)], it)
+}
+
+fn parse_class(it: Chars<'_>) -> ParseResult<'_, Vec<GroupingElement>> {
+    match it.next() {
+        Some(('\\', next)) => {
+            if let Some((ch @ ('p' | 'P'), it)) = next.next() {
+                let (property, it) = parse_property_name(it)?;
+                return Ok((vec![GroupingElement::Property{ negated: ch == 'P', property }], it));
+            }
+            match next.next().map(|(c, it)| (class::shorthand_class(c), it)) {
+                Some((Some((false, elements)), it)) => Ok((elements.to_vec(), it)),
+                Some((Some((true, elements)), it)) => Ok((class::complement(elements), it)),
+                _ => Err(next.error(EXPECT_CLASS)),
+            }
+        },
+
+        Some(('[', next)) => {
+            let name_start = match next.next() {
+                Some((':', it)) => it,
+                _ => return Err(next.error(&["':'"])),
+            };
+            let mut name_end = name_start.clone();
+            while let Some((c, it)) = name_end.next() {
+                if !c.is_ascii_alphabetic() {
+                    break;
+                }
+                name_end = it;
+            }
+            let name = &name_start.it.as_str()[..(name_end.position() - name_start.position())];
+            let elements = class::posix_class(name).ok_or_else(|| name_start.error(&["a POSIX class name"]))?;
+            match name_end.next() {
+                Some((':', it)) => match it.next() {
+                    Some((']', it)) => Ok((elements.to_vec(), it)),
+                    _ => Err(it.error(&["']'"])),
+                },
+                _ => Err(name_end.error(&["':'"])),
+            }
+        },
+
+        _ => Err(it.error(EXPECT_CLASS)),
+    }
+}
+
+// Parses the property name after '\p' or '\P', either a single character or
+// a name between braces
+fn parse_property_name(it: Chars<'_>) -> ParseResult<'_, Property> {
+    let (name_start, name, rest) = match it.next() {
+        Some(('{', inner)) => {
+            let body = inner.it.as_str();
+            match body.find('}') {
+                Some(len) => (inner.clone(), &body[..len], inner.skip(len + 1)),
+                None => return Err(inner.skip(body.len()).error(&["'}'"])),
+            }
+        },
+        Some((c, rest)) if c.is_ascii_alphabetic() => (it.clone(), &it.it.as_str()[..1], rest),
+        _ => return Err(it.error(&["'{'", "a Unicode property name"])),
+    };
+    match class::unicode_property(name) {
+        Some(property) => Ok((property, rest)),
+        None => Err(name_start.error(EXPECT_PROPERTY)),
+    }
 }
 
 fn parse_grouping_atom_init(it: Chars<'_>) -> ParseResult<'_, char> {
@@ -249,33 +622,68 @@ fn parse_grouping_atom_init(it: Chars<'_>) -> ParseResult<'_, char> {
 
 fn parse_grouping_atom(it: Chars<'_>) -> ParseResult<'_, char> {
     match it.next() {
-        Some((']', _)) => Err("A character was expected in grouping!"),
+        Some((']', _)) => Err(it.error(EXPECT_GROUPING_ATOM)),
 
-        Some(('\\', it)) => {
-            if let Some((ch, it)) = it.next() {
-                if let Some(ch) = to_escaped(ch) {
-                    Ok((ch, it))
-                }
-                else {
-                    Err("Cannot escape character!")
-                }
+        Some(('\\', next)) => parse_escape(next),
+
+        Some((c, next)) => {
+            if is_nonspecial_grouping_char(c) {
+                Ok((c, next))
             }
             else {
-                Err("Expected a character after '\'!")
+                Err(it.error(EXPECT_GROUPING_ATOM))
             }
         },
 
-        Some((c, it)) => {
-            if is_nonspecial_char(c) {
-                Ok((c, it))
-            }
-            else {
-                Err("A nonspecial character was expected in grouping!")
+        None => Err(it.error(EXPECT_GROUPING_ATOM)),
+    }
+}
+
+// Parses an escape sequence, right after the '\'
+fn parse_escape(it: Chars<'_>) -> ParseResult<'_, char> {
+    match it.next() {
+        Some(('x', next)) => {
+            let (code, rest) = parse_hex_digits(next.clone(), 2, 2)?;
+            // Two hex digits are always a valid character
+            Ok((std::char::from_u32(code).unwrap(), rest))
+        },
+
+        Some(('u', next)) => {
+            let digits = match next.next() {
+                Some(('{', digits)) => digits,
+                _ => return Err(next.error(&["'{'"])),
+            };
+            let (code, rest) = parse_hex_digits(digits.clone(), 1, 6)?;
+            let ch = std::char::from_u32(code).ok_or_else(|| digits.error(&["a valid code point"]))?;
+            match rest.next() {
+                Some(('}', rest)) => Ok((ch, rest)),
+                _ => Err(rest.error(&["'}'"])),
             }
         },
 
-        None => Err("A character was expected in grouping!"),
+        Some((c, next)) => match to_escaped(c) {
+            Some(ch) => Ok((ch, next)),
+            None => Err(it.error(EXPECT_ESCAPABLE)),
+        },
+
+        None => Err(it.error(EXPECT_ESCAPABLE)),
+    }
+}
+
+// Parses at least min, at most max hexadecimal digits as a number
+fn parse_hex_digits(mut it: Chars<'_>, min: usize, max: usize) -> ParseResult<'_, u32> {
+    let mut code = 0;
+    for i in 0..max {
+        match it.next().and_then(|(c, next)| c.to_digit(16).map(|d| (d, next))) {
+            Some((d, next)) => {
+                code = code * 16 + d;
+                it = next;
+            },
+            None if i < min => return Err(it.error(EXPECT_HEX_DIGIT)),
+            None => break,
+        }
     }
+    Ok((code, it))
 }
 
 fn to_escaped(c: char) -> Option<char> {
@@ -288,6 +696,8 @@ fn to_escaped(c: char) -> Option<char> {
             'n' => Some('\n'),
             't' => Some('\t'),
             '0' => Some('\0'),
+            // Only special inside groupings, but escaping them is allowed anywhere
+            '-' | '^' => Some(c),
             _ => None,
         }
     }
@@ -297,8 +707,13 @@ fn is_nonspecial_char(c: char) -> bool {
     !c.is_control() && !is_special_char(c)
 }
 
-fn is_special_char(c: char) -> bool {
-    "()[]?*+|".contains(c)
+// Inside groupings the operators have no meaning, like in "[+*.]"
+fn is_nonspecial_grouping_char(c: char) -> bool {
+    !c.is_control() && !"[]\\".contains(c)
+}
+
+pub(crate) fn is_special_char(c: char) -> bool {
+    "()[]{}?*+|&~.\\".contains(c)
 }
 
 // Tests ///////////////////////////////////////////////////////////////////////
@@ -347,6 +762,14 @@ mod regex_parser_tests {
         Box::new(Node::Quantified{ subnode, quantifier: Quantifier::Between(0, 1) })
     }
 
+    fn and(first: Box<Node>, second: Box<Node>) -> Box<Node> {
+        Box::new(Node::Intersection{ first, second })
+    }
+
+    fn not(subnode: Box<Node>) -> Box<Node> {
+        Box::new(Node::Complement{ subnode })
+    }
+
     /**
      * Actual tests.
      */
@@ -410,4 +833,356 @@ mod regex_parser_tests {
     fn ab_zero_or_more() {
         assert_eq!(parse(r"(ab)*"), Ok(*star(seq(ch('a'), ch('b')))));
     }
+
+    #[test]
+    fn not_a_to_z_group() {
+        assert_eq!(parse(r"[^a-z]"), Ok(*grp(true, vec![ge_rng('a', 'z')])));
+    }
+
+    #[test]
+    fn operators_in_group() {
+        assert_eq!(parse(r"[+*?(){}|-]"), Ok(*grp(false, "+*?(){}|-".chars().map(ge_ch).collect())));
+    }
+
+    #[test]
+    fn not_bracket_group() {
+        assert_eq!(parse(r"[^]a]"), Ok(*grp(true, vec![ge_ch(']'), ge_ch('a')])));
+    }
+
+    /**
+     * Error tests.
+     */
+
+    fn err(source: &str) -> ParseError {
+        parse(source).unwrap_err()
+    }
+
+    #[test]
+    fn unmatched_close_paren() {
+        let e = err(r"ab)");
+        assert_eq!((e.position, e.found), (2, Some(')')));
+        assert!(e.expected.contains(&"the end of the regex"));
+        assert!(e.expected.contains(&"a quantifier"));
+    }
+
+    #[test]
+    fn unclosed_paren() {
+        let e = err(r"x(ab");
+        assert_eq!((e.position, e.found), (4, None));
+        assert!(e.expected.contains(&"')'"));
+    }
+
+    #[test]
+    fn unclosed_grouping() {
+        let e = err(r"[a-c");
+        assert_eq!((e.position, e.found), (4, None));
+        assert!(e.expected.contains(&"']'"));
+    }
+
+    #[test]
+    fn invalid_escape() {
+        let e = err(r"ab\q");
+        assert_eq!(e, ParseError{ position: 3, found: Some('q'), expected: vec!["an escapable character"] });
+    }
+
+    #[test]
+    fn position_is_in_bytes() {
+        let e = err("ő*)");
+        assert_eq!((e.position, e.found), (3, Some(')')));
+    }
+
+    #[test]
+    fn error_message() {
+        assert_eq!(err(r"\q").to_string(), "Unexpected 'q' at byte 1, expected an escapable character!");
+    }
+
+    /**
+     * Character class tests.
+     */
+
+    fn digits() -> Vec<GroupingElement> {
+        vec![ge_rng('0', '9')]
+    }
+
+    #[test]
+    fn shorthand_outside_grouping() {
+        assert_eq!(parse(r"\d+"), Ok(*plus(grp(false, digits()))));
+        assert_eq!(parse(r"\D"), Ok(*grp(true, digits())));
+    }
+
+    #[test]
+    fn shorthand_inside_grouping() {
+        assert_eq!(parse(r"[\s-]"), Ok(*grp(false, vec![ge_rng('\t', '\r'), ge_ch(' '), ge_ch('-')])));
+        assert_eq!(parse(r"[\Dx]"), Ok(*grp(false, vec![ge_rng('\0', '/'), ge_rng(':', std::char::MAX), ge_ch('x')])));
+    }
+
+    #[test]
+    fn posix_class() {
+        assert_eq!(parse(r"[[:alpha:]_]"), Ok(*grp(false, vec![ge_rng('A', 'Z'), ge_rng('a', 'z'), ge_ch('_')])));
+        assert_eq!(parse(r"[^[:digit:]]"), Ok(*grp(true, digits())));
+        assert_eq!(parse(r"[a[:digit:]]"), Ok(*grp(false, vec![ge_ch('a'), ge_rng('0', '9')])));
+    }
+
+    #[test]
+    fn unknown_posix_class() {
+        let e = err(r"[[:alfa:]]");
+        assert_eq!((e.position, e.found), (3, Some('a')));
+        assert!(e.expected.contains(&"a POSIX class name"));
+    }
+
+    /**
+     * Unicode property tests.
+     */
+
+    fn prop(name: &str) -> Property {
+        class::unicode_property(name).unwrap()
+    }
+
+    fn ge_prop(negated: bool, name: &str) -> GroupingElement {
+        GroupingElement::Property{ negated, property: prop(name) }
+    }
+
+    #[test]
+    fn property_outside_grouping() {
+        assert_eq!(parse(r"\p{L}"), Ok(*grp(false, vec![ge_prop(false, "L")])));
+        assert_eq!(parse(r"\PL"), Ok(*grp(true, vec![ge_prop(false, "L")])));
+        assert_eq!(parse(r"\p{Greek}+"), Ok(*plus(grp(false, vec![ge_prop(false, "Greek")]))));
+    }
+
+    #[test]
+    fn property_inside_grouping() {
+        assert_eq!(
+            parse(r"[\p{XID_Start}_\P{Nd}]"),
+            Ok(*grp(false, vec![ge_prop(false, "XID_Start"), ge_ch('_'), ge_prop(true, "Nd")]))
+        );
+    }
+
+    #[test]
+    fn unknown_property() {
+        let e = err(r"a\p{Klingon}");
+        assert_eq!((e.position, e.found), (4, Some('K')));
+        assert!(e.expected.contains(&"a Unicode property name"));
+    }
+
+    #[test]
+    fn unclosed_property() {
+        let e = err(r"\p{Greek");
+        assert_eq!((e.position, e.found), (8, None));
+        assert!(e.expected.contains(&"'}'"));
+    }
+
+    /**
+     * Counted repetition tests.
+     */
+
+    fn rep(subnode: Box<Node>, quantifier: Quantifier) -> Box<Node> {
+        Box::new(Node::Quantified{ subnode, quantifier })
+    }
+
+    #[test]
+    fn exactly_n() {
+        assert_eq!(parse(r"a{3}"), Ok(*rep(ch('a'), Quantifier::Between(3, 3))));
+    }
+
+    #[test]
+    fn at_least_n() {
+        assert_eq!(parse(r"a{2,}"), Ok(*rep(ch('a'), Quantifier::AtLeast(2))));
+    }
+
+    #[test]
+    fn between_n_and_m() {
+        assert_eq!(parse(r"(ab){2,15}c"), Ok(*seq(rep(seq(ch('a'), ch('b')), Quantifier::Between(2, 15)), ch('c'))));
+    }
+
+    #[test]
+    fn hex_escape() {
+        let hex = || grp(false, vec![ge_rng('0', '9'), ge_rng('a', 'f')]);
+        assert_eq!(
+            parse(r"u\{[0-9a-f]{4}\}"),
+            Ok(*seq(ch('u'), seq(ch('{'), seq(rep(hex(), Quantifier::Between(4, 4)), ch('}')))))
+        );
+    }
+
+    #[test]
+    fn reversed_bounds() {
+        let e = err(r"a{5,2}");
+        assert_eq!((e.position, e.found), (4, Some('2')));
+        assert_eq!(e.expected, vec!["an upper bound not less than the lower bound"]);
+    }
+
+    #[test]
+    fn malformed_counts() {
+        assert_eq!(err(r"a{}").position, 2);
+        assert_eq!(err(r"a{2").position, 3);
+        assert_eq!(err(r"a{2,x}").position, 4);
+        assert_eq!(err(r"a{99999999999999999999999}").position, 2);
+    }
+
+    #[test]
+    fn count_limit() {
+        assert_eq!(parse(r"a{1000}"), Ok(*rep(ch('a'), Quantifier::Between(1000, 1000))));
+        let e = err(r"a{1001}");
+        assert_eq!((e.position, e.found), (2, Some('1')));
+        assert!(e.expected.contains(&"a repetition count of at most 1000"));
+        assert_eq!(err(r"a{2,4294967296}").position, 4);
+        assert_eq!(err(r"a{4000,}").position, 2);
+        assert!(EXPECT_REPETITION_COUNT[0].ends_with(&MAX_REPETITION.to_string()));
+    }
+
+    /**
+     * Wildcard and escape sequence tests.
+     */
+
+    #[test]
+    fn wildcard() {
+        assert_eq!(parse(r"a.b"), Ok(*seq(ch('a'), seq(grp(true, vec![ge_ch('\n')]), ch('b')))));
+        assert_eq!(parse(r"[.]"), Ok(*grp(false, vec![ge_ch('.')])));
+        assert_eq!(parse(r"\."), Ok(*ch('.')));
+        assert_eq!(escape("a.b"), r"a\.b");
+    }
+
+    #[test]
+    fn wildcard_matching_newline() {
+        let flags = Flags{ dot_matches_newline: true, ..Flags::default() };
+        assert_eq!(parse_with_flags(r".*", flags), Ok(*star(grp(false, vec![ge_rng('\0', std::char::MAX)]))));
+    }
+
+    #[test]
+    fn control_escapes() {
+        assert_eq!(parse(r"[ \r\n\t\0]"), Ok(*grp(false, vec![ge_ch(' '), ge_ch('\r'), ge_ch('\n'), ge_ch('\t'), ge_ch('\0')])));
+    }
+
+    #[test]
+    fn code_point_escapes() {
+        assert_eq!(parse(r"\x41\u{151}"), Ok(*seq(ch('A'), ch('ő'))));
+        assert_eq!(parse(r"[\x00-\x1F\u{10FFFF}]"), Ok(*grp(false, vec![ge_rng('\0', '\x1F'), ge_ch(std::char::MAX)])));
+        assert_eq!(parse(r"\x7f+"), Ok(*plus(ch('\x7F'))));
+    }
+
+    #[test]
+    fn malformed_code_point_escapes() {
+        assert_eq!(err(r"\x4").position, 3);
+        assert_eq!(err(r"\xg1").position, 2);
+        assert_eq!(err(r"\u41").position, 2);
+        assert_eq!(err(r"\u{}").position, 3);
+        assert_eq!(err(r"\u{0000041}").position, 9);
+        let e = err(r"\u{D800}");
+        assert_eq!((e.position, e.expected), (3, vec!["a valid code point"]));
+    }
+
+    /**
+     * Case-insensitivity tests.
+     */
+
+    #[test]
+    fn case_insensitive_literals() {
+        let flags = Flags{ case_insensitive: true, ..Flags::default() };
+        assert_eq!(
+            parse_with_flags(r"a1", flags),
+            Ok(*seq(grp(false, vec![ge_ch('a'), ge_ch('A')]), ch('1')))
+        );
+        assert_eq!(parse_with_flags(r"\u{3A3}", flags), Ok(*grp(false, vec![ge_ch('Σ'), ge_ch('ς'), ge_ch('σ')])));
+    }
+
+    #[test]
+    fn case_insensitive_grouping() {
+        let flags = Flags{ case_insensitive: true, ..Flags::default() };
+        assert_eq!(parse_with_flags(r"[^a-b]", flags), Ok(*grp(true, vec![ge_rng('a', 'b'), ge_ch('A'), ge_ch('B')])));
+    }
+
+    #[test]
+    fn inline_flags() {
+        assert_eq!(parse(r"(?i)a"), Ok(*grp(false, vec![ge_ch('a'), ge_ch('A')])));
+        assert_eq!(parse(r"(?i)a(?-i)b"), Ok(*seq(grp(false, vec![ge_ch('a'), ge_ch('A')]), ch('b'))));
+        assert_eq!(parse(r"(?s)."), Ok(*grp(false, vec![ge_rng('\0', std::char::MAX)])));
+    }
+
+    #[test]
+    fn inline_flags_are_scoped() {
+        assert_eq!(parse(r"((?i)a)b"), Ok(*seq(grp(false, vec![ge_ch('a'), ge_ch('A')]), ch('b'))));
+        assert_eq!(parse(r"(?i)a|b"), Ok(*alt(grp(false, vec![ge_ch('a'), ge_ch('A')]), grp(false, vec![ge_ch('b'), ge_ch('B')]))));
+    }
+
+    #[test]
+    fn unknown_inline_flag() {
+        let e = err(r"a(?x)");
+        assert_eq!((e.position, e.found), (3, Some('x')));
+        assert!(e.expected.contains(&"a flag"));
+    }
+
+    /**
+     * Fragment reference tests.
+     */
+
+    fn fragments(name: &str) -> Option<Node> {
+        match name {
+            "DIGIT" => Some(*grp(false, digits())),
+            "sign_2" => Some(*alt(ch('+'), ch('-'))),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn fragment_reference() {
+        assert_eq!(
+            parse_with_fragments(r"{sign_2}?{DIGIT}+", Flags::default(), fragments),
+            Ok(*seq(qmark(alt(ch('+'), ch('-'))), plus(grp(false, digits()))))
+        );
+    }
+
+    #[test]
+    fn fragment_reference_after_atom() {
+        assert_eq!(
+            parse_with_fragments(r"x{DIGIT}{2}", Flags::default(), fragments),
+            Ok(*seq(ch('x'), rep(grp(false, digits()), Quantifier::Between(2, 2))))
+        );
+    }
+
+    #[test]
+    fn undefined_fragment() {
+        let e = parse_with_fragments(r"a{DIGITS}", Flags::default(), fragments).unwrap_err();
+        assert_eq!((e.position, e.found), (2, Some('D')));
+        assert!(e.expected.contains(&"a defined fragment name"));
+        assert_eq!(err(r"{DIGIT}").position, 1);
+    }
+
+    #[test]
+    fn malformed_fragment_reference() {
+        assert_eq!(parse_with_fragments(r"{DIGIT", Flags::default(), fragments).unwrap_err().position, 6);
+        assert_eq!(parse_with_fragments(r"{-}", Flags::default(), fragments).unwrap_err().position, 1);
+    }
+
+    /**
+     * Intersection and complement tests.
+     */
+
+    #[test]
+    fn intersection() {
+        assert_eq!(parse(r"a&b&c"), Ok(*and(ch('a'), and(ch('b'), ch('c')))));
+    }
+
+    #[test]
+    fn intersection_precedence() {
+        assert_eq!(parse(r"ab&c|d"), Ok(*alt(and(seq(ch('a'), ch('b')), ch('c')), ch('d'))));
+    }
+
+    #[test]
+    fn complement() {
+        assert_eq!(parse(r"~a*b"), Ok(*seq(not(star(ch('a'))), ch('b'))));
+        assert_eq!(parse(r"a~~(b)"), Ok(*seq(ch('a'), not(not(ch('b'))))));
+    }
+
+    #[test]
+    fn escaped_operators() {
+        assert_eq!(parse(r"\&\~[&~]"), Ok(*seq(ch('&'), seq(ch('~'), grp(false, vec![ge_ch('&'), ge_ch('~')])))));
+    }
+
+    #[test]
+    fn dangling_operators() {
+        let e = err(r"a&");
+        assert_eq!((e.position, e.found), (2, None));
+        assert!(e.expected.contains(&"'~'"));
+        assert_eq!(err(r"~").position, 1);
+        assert_eq!(err(r"&a").position, 0);
+    }
 }
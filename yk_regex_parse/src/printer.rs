@@ -0,0 +1,197 @@
+/**
+ * Printing the regex AST back as regex text. The printed text parses back to
+ * the same tree, using only the parentheses needed for that.
+ */
+
+use std::fmt;
+use crate::ast::{Node, Quantifier, GroupingElement};
+use crate::parser::is_special_char;
+
+// How tightly a node binds, the parser is right-associative for alternatives,
+// intersections and sequences
+fn precedence(node: &Node) -> u8 {
+    match node {
+        Node::Alternative{ .. } => 0,
+        Node::Intersection{ .. } => 1,
+        Node::Sequence{ .. } => 2,
+        Node::Complement{ .. } => 3,
+        Node::Quantified{ .. } => 4,
+        Node::Grouping{ .. } | Node::Literal(_) => 5,
+    }
+}
+
+// Writes the node, parenthesized if it binds looser than required
+fn write_child(f: &mut fmt::Formatter<'_>, node: &Node, min_precedence: u8) -> fmt::Result {
+    if precedence(node) < min_precedence {
+        write!(f, "({})", node)
+    }
+    else {
+        write!(f, "{}", node)
+    }
+}
+
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Node::Alternative{ first, second } => {
+                write_child(f, first, 1)?;
+                write!(f, "|")?;
+                write_child(f, second, 0)
+            },
+
+            Node::Intersection{ first, second } => {
+                write_child(f, first, 2)?;
+                write!(f, "&")?;
+                write_child(f, second, 1)
+            },
+
+            Node::Sequence{ first, second } => {
+                write_child(f, first, 3)?;
+                write_child(f, second, 2)
+            },
+
+            Node::Complement{ subnode } => {
+                write!(f, "~")?;
+                write_child(f, subnode, 3)
+            },
+
+            Node::Quantified{ subnode, quantifier } => {
+                write_child(f, subnode, 5)?;
+                write!(f, "{}", quantifier)
+            },
+
+            Node::Grouping{ negated, elements } => {
+                // An empty grouping has no syntax, but we can spell out the
+                // full range instead
+                if elements.is_empty() {
+                    let negated = if *negated { "" } else { "^" };
+                    return write!(f, "[{}\\0-\\u{{10FFFF}}]", negated);
+                }
+                write!(f, "[")?;
+                if *negated {
+                    write!(f, "^")?;
+                }
+                for element in elements {
+                    write!(f, "{}", element)?;
+                }
+                write!(f, "]")
+            },
+
+            Node::Literal(c) => write_char(f, *c, false),
+        }
+    }
+}
+
+impl fmt::Display for Quantifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Quantifier::AtLeast(0) => write!(f, "*"),
+            Quantifier::AtLeast(1) => write!(f, "+"),
+            Quantifier::AtLeast(n) => write!(f, "{{{},}}", n),
+            Quantifier::Between(0, 1) => write!(f, "?"),
+            Quantifier::Between(n, m) if n == m => write!(f, "{{{}}}", n),
+            Quantifier::Between(n, m) => write!(f, "{{{},{}}}", n, m),
+        }
+    }
+}
+
+impl fmt::Display for GroupingElement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GroupingElement::Literal(c) => write_char(f, *c, true),
+            GroupingElement::Range(a, b) => {
+                write_char(f, *a, true)?;
+                write!(f, "-")?;
+                write_char(f, *b, true)
+            },
+            GroupingElement::Property{ negated: false, property } => write!(f, "\\p{{{}}}", property.name()),
+            GroupingElement::Property{ negated: true, property } => write!(f, "\\P{{{}}}", property.name()),
+        }
+    }
+}
+
+// Writes a single character, escaped if needed. Inside groupings '-' and '^'
+// are escaped too, so they can't be mistaken for a range or a negation.
+fn write_char(f: &mut fmt::Formatter<'_>, c: char, in_grouping: bool) -> fmt::Result {
+    match c {
+        '\n' => write!(f, "\\n"),
+        '\r' => write!(f, "\\r"),
+        '\t' => write!(f, "\\t"),
+        '\0' => write!(f, "\\0"),
+        '-' | '^' if in_grouping => write!(f, "\\{}", c),
+        c if is_special_char(c) => write!(f, "\\{}", c),
+        c if c.is_control() => write!(f, "\\u{{{:X}}}", c as u32),
+        c => write!(f, "{}", c),
+    }
+}
+
+// Tests ///////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod printer_tests {
+    use super::*;
+    use crate::parser::parse;
+
+    // Parses, prints and checks both the text and that it parses back the same
+    fn round_trip(source: &str, expected: &str) {
+        let node = parse(source).unwrap();
+        let printed = node.to_string();
+        assert_eq!(printed, expected);
+        assert_eq!(parse(&printed), Ok(node));
+    }
+
+    fn seq(first: Node, second: Node) -> Node {
+        Node::Sequence{ first: Box::new(first), second: Box::new(second) }
+    }
+
+    fn alt(first: Node, second: Node) -> Node {
+        Node::Alternative{ first: Box::new(first), second: Box::new(second) }
+    }
+
+    #[test]
+    fn minimal_parentheses() {
+        round_trip(r"ab|c", r"ab|c");
+        round_trip(r"a(b|c)d", r"a(b|c)d");
+        round_trip(r"((a))(bc)*", r"a(bc)*");
+        round_trip(r"(a|b)|c", r"(a|b)|c");
+        round_trip(r"(a*)?", r"(a*)?");
+        round_trip(r"(a|b)&c(d&e)", r"(a|b)&c(d&e)");
+        round_trip(r"(~a)*~(b*)(~c)d", r"(~a)*~b*~cd");
+        round_trip(r"~(ab)&~(~c)", r"~(ab)&~~c");
+    }
+
+    #[test]
+    fn quantifiers() {
+        round_trip(r"a*b+c?d{3}e{2,}f{1,4}", r"a*b+c?d{3}e{2,}f{1,4}");
+    }
+
+    #[test]
+    fn groupings() {
+        round_trip(r"[^a-z_]", r"[^a-z_]");
+        round_trip(r"\d", r"[0-9]");
+        round_trip(r"[\p{Greek}\PL]", r"[\p{Greek}\P{L}]");
+        round_trip(r"[]\-\^]", r"[\]\-\^]");
+    }
+
+    #[test]
+    fn escaped_characters() {
+        round_trip(r"\(\.\\\n\x7F", r"\(\.\\\n\u{7F}");
+        round_trip(r"\&\~[&~]", r"\&\~[\&\~]");
+        round_trip(r".", r"[^\n]");
+    }
+
+    #[test]
+    fn left_nested_trees() {
+        let node = seq(seq(Node::Literal('a'), Node::Literal('b')), Node::Literal('c'));
+        assert_eq!(node.to_string(), "(ab)c");
+        assert_eq!(parse("(ab)c"), Ok(node));
+        let node = alt(alt(Node::Literal('a'), Node::Literal('b')), Node::Literal('c'));
+        assert_eq!(parse(&node.to_string()), Ok(node));
+    }
+
+    #[test]
+    fn empty_groupings() {
+        assert_eq!(Node::Grouping{ negated: false, elements: vec![] }.to_string(), r"[^\0-\u{10FFFF}]");
+        assert_eq!(Node::Grouping{ negated: true, elements: vec![] }.to_string(), r"[\0-\u{10FFFF}]");
+    }
+}
@@ -0,0 +1,178 @@
+/**
+ * A simplification pass over the regex AST. It keeps the matched language,
+ * but produces smaller trees, which means smaller automata and more readable
+ * output:
+ *  - Sequences and alternatives are flattened to the right-nested form the
+ *    parser produces.
+ *  - Alternatives of single characters are merged into one grouping.
+ *  - Groupings of a single character become literals.
+ *  - Quantifiers of exactly one repetition are removed.
+ *  - Double complements cancel out.
+ */
+
+use crate::ast::{Node, Quantifier, GroupingElement};
+
+/// Simplifies the regex AST without changing what it matches.
+pub fn simplify(node: Node) -> Node {
+    match node {
+        Node::Alternative{ .. } => {
+            let mut branches = Vec::new();
+            flatten_alternative(node, &mut branches);
+            rebuild(merge_characters(branches), |first, second| Node::Alternative{ first, second })
+        },
+
+        Node::Sequence{ .. } => {
+            let mut items = Vec::new();
+            flatten_sequence(node, &mut items);
+            rebuild(items, |first, second| Node::Sequence{ first, second })
+        },
+
+        Node::Intersection{ first, second } =>
+            Node::Intersection{ first: Box::new(simplify(*first)), second: Box::new(simplify(*second)) },
+
+        Node::Complement{ subnode } => match simplify(*subnode) {
+            Node::Complement{ subnode } => *subnode,
+            subnode => Node::Complement{ subnode: Box::new(subnode) },
+        },
+
+        Node::Quantified{ subnode, quantifier: Quantifier::Between(1, 1) } => simplify(*subnode),
+
+        Node::Quantified{ subnode, quantifier } =>
+            Node::Quantified{ subnode: Box::new(simplify(*subnode)), quantifier },
+
+        Node::Grouping{ negated: false, elements } if elements.len() == 1 => match elements[0] {
+            GroupingElement::Literal(c) => Node::Literal(c),
+            _ => Node::Grouping{ negated: false, elements },
+        },
+
+        node => node,
+    }
+}
+
+// Collects the simplified branches of nested alternatives
+fn flatten_alternative(node: Node, branches: &mut Vec<Node>) {
+    match node {
+        Node::Alternative{ first, second } => {
+            flatten_alternative(*first, branches);
+            flatten_alternative(*second, branches);
+        },
+        node => match simplify(node) {
+            // Simplifying can uncover further alternatives
+            node @ Node::Alternative{ .. } => flatten_alternative(node, branches),
+            node => branches.push(node),
+        },
+    }
+}
+
+// Collects the simplified items of nested sequences
+fn flatten_sequence(node: Node, items: &mut Vec<Node>) {
+    match node {
+        Node::Sequence{ first, second } => {
+            flatten_sequence(*first, items);
+            flatten_sequence(*second, items);
+        },
+        node => match simplify(node) {
+            node @ Node::Sequence{ .. } => flatten_sequence(node, items),
+            node => items.push(node),
+        },
+    }
+}
+
+// Merges all the branches that match a single character into one grouping, in
+// place of the first one
+fn merge_characters(branches: Vec<Node>) -> Vec<Node> {
+    let mut result = Vec::new();
+    let mut merged: Option<(usize, Vec<GroupingElement>)> = None;
+    for branch in branches {
+        let elements = match branch {
+            Node::Literal(c) => vec![GroupingElement::Literal(c)],
+            Node::Grouping{ negated: false, elements } => elements,
+            branch => {
+                result.push(branch);
+                continue;
+            },
+        };
+        match &mut merged {
+            Some((_, merged_elements)) => {
+                for element in elements {
+                    if !merged_elements.contains(&element) {
+                        merged_elements.push(element);
+                    }
+                }
+            },
+            None => {
+                merged = Some((result.len(), elements));
+                // Placeholder, filled in after all branches are seen
+                result.push(Node::Grouping{ negated: false, elements: Vec::new() });
+            },
+        }
+    }
+    if let Some((idx, elements)) = merged {
+        result[idx] = simplify(Node::Grouping{ negated: false, elements });
+    }
+    result
+}
+
+// Builds the right-nested tree from a non-empty list of nodes
+fn rebuild<F>(nodes: Vec<Node>, combine: F) -> Node where F : Fn(Box<Node>, Box<Node>) -> Node {
+    let mut nodes = nodes.into_iter().rev();
+    let last = nodes.next().expect("Can't build a tree from no nodes!");
+    nodes.fold(last, |second, first| combine(Box::new(first), Box::new(second)))
+}
+
+// Tests ///////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod simplify_tests {
+    use super::*;
+    use crate::parser::parse;
+
+    // Simplifies the parsed regex and compares the printed result
+    fn simplified(source: &str) -> String {
+        simplify(parse(source).unwrap()).to_string()
+    }
+
+    #[test]
+    fn flatten_sequences() {
+        assert_eq!(simplified(r"(ab)(c(de))"), "abcde");
+        assert_eq!(simplify(parse(r"(ab)c").unwrap()), parse(r"abc").unwrap());
+    }
+
+    #[test]
+    fn flatten_alternatives() {
+        assert_eq!(simplified(r"(ab|cd)|(ef|gh)"), "ab|cd|ef|gh");
+    }
+
+    #[test]
+    fn merge_single_characters() {
+        assert_eq!(simplified(r"a|b|[c-e]|xy|\d"), "[abc-e0-9]|xy");
+        assert_eq!(simplified(r"(a|b)*"), "[ab]*");
+    }
+
+    #[test]
+    fn keep_negated_groupings() {
+        assert_eq!(simplified(r"a|[^b]"), "a|[^b]");
+    }
+
+    #[test]
+    fn single_character_groupings() {
+        assert_eq!(simplified(r"[a]b"), "ab");
+        assert_eq!(simplified(r"a|(a)"), "a");
+    }
+
+    #[test]
+    fn collapse_single_repetition() {
+        assert_eq!(simplified(r"(ab){1}c{1,1}d{1,2}"), "abcd{1,2}");
+    }
+
+    #[test]
+    fn intersections_and_complements() {
+        assert_eq!(simplified(r"((a|b)c)&~~(a{1}|b)"), "[ab]c&[ab]");
+        assert_eq!(simplified(r"~~~(a)"), "~a");
+    }
+
+    #[test]
+    fn nested_simplifications() {
+        assert_eq!(simplified(r"((a|b)c){1}|(d|(e{1}))"), "[ab]c|[de]");
+    }
+}